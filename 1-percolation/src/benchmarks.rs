@@ -1,33 +1,55 @@
 use test::Bencher;
-use percolation;
+use percolation::{self, Simulation};
+use rand;
+use unionfind::UnionFindKind;
 
 
 #[bench]
 fn simulate_percolation_of_size_10(b: &mut Bencher) {
-    b.iter(|| percolation::simulate(100));
+    b.iter(|| percolation::simulate(&Simulation::new(100), &mut rand::thread_rng()));
 }
 
 #[bench]
 fn simulate_percolation_of_size_100(b: &mut Bencher) {
-    b.iter(|| percolation::simulate(100));
+    b.iter(|| percolation::simulate(&Simulation::new(100), &mut rand::thread_rng()));
 }
 
 #[bench]
 fn simulate_percolation_of_size_200(b: &mut Bencher) {
-    b.iter(|| percolation::simulate(200));
+    b.iter(|| percolation::simulate(&Simulation::new(200), &mut rand::thread_rng()));
 }
 
 #[bench]
 fn simulate_10_percolations_of_size_200_in_1_job(b: &mut Bencher) {
-    b.iter(|| percolation::simulate_multiple(200, 10, 1));
+    b.iter(|| percolation::simulate_multiple(&Simulation::new(200), 10, 1, 0));
 }
 
 #[bench]
 fn simulate_10_percolations_of_size_200_in_4_jobs(b: &mut Bencher) {
-    b.iter(|| percolation::simulate_multiple(200, 10, 4));
+    b.iter(|| percolation::simulate_multiple(&Simulation::new(200), 10, 4, 0));
 }
 
 #[bench]
 fn simulate_10_percolations_of_size_200_in_8_jobs(b: &mut Bencher) {
-    b.iter(|| percolation::simulate_multiple(200, 10, 8));
+    b.iter(|| percolation::simulate_multiple(&Simulation::new(200), 10, 8, 0));
+}
+
+#[bench]
+fn simulate_percolation_of_size_100_with_quick_find(b: &mut Bencher) {
+    b.iter(|| percolation::simulate(&Simulation { union_find: UnionFindKind::QuickFind, ..Simulation::new(100) }, &mut rand::thread_rng()));
+}
+
+#[bench]
+fn simulate_percolation_of_size_100_with_quick_union(b: &mut Bencher) {
+    b.iter(|| percolation::simulate(&Simulation { union_find: UnionFindKind::QuickUnion, ..Simulation::new(100) }, &mut rand::thread_rng()));
+}
+
+#[bench]
+fn simulate_percolation_of_size_100_with_weighted_quick_union(b: &mut Bencher) {
+    b.iter(|| percolation::simulate(&Simulation::new(100), &mut rand::thread_rng()));
+}
+
+#[bench]
+fn simulate_percolation_of_size_100_with_weighted_quick_union_path_compression(b: &mut Bencher) {
+    b.iter(|| percolation::simulate(&Simulation { union_find: UnionFindKind::WeightedQuickUnionPathCompression, ..Simulation::new(100) }, &mut rand::thread_rng()));
 }
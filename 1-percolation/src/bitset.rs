@@ -0,0 +1,84 @@
+use std::iter;
+use std::mem;
+
+const WORD_BITS: usize = 64;
+
+/// A fixed number of bits packed 64 to a word, so that it takes an eighth of the memory of a `Vec<bool>`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BitSet {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl BitSet {
+    /// Creates a set of `len` bits which are all clear.
+    pub fn new(len: usize) -> BitSet {
+        BitSet { words: iter::repeat(0).take(words(len)).collect(), len: len }
+    }
+
+    /// Returns the number of bytes a set of `len` bits would take up on the heap, without creating one.
+    pub fn memory_usage_of(len: usize) -> usize {
+        words(len) * mem::size_of::<u64>()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn get(&self, bit: usize) -> bool {
+        assert!(bit < self.len, "Bit {} is out of bounds for a set of {} bits", bit, self.len);
+        self.words[bit / WORD_BITS] & (1 << (bit % WORD_BITS)) != 0
+    }
+
+    pub fn set(&mut self, bit: usize, value: bool) {
+        assert!(bit < self.len, "Bit {} is out of bounds for a set of {} bits", bit, self.len);
+        let mask = 1 << (bit % WORD_BITS);
+        if value {
+            self.words[bit / WORD_BITS] |= mask;
+        } else {
+            self.words[bit / WORD_BITS] &= !mask;
+        }
+    }
+
+    /// Returns the number of bits which are set.
+    #[cfg(test)]
+    pub fn count_ones(&self) -> usize {
+        self.words.iter().fold(0, |count, &word| count + word.count_ones() as usize)
+    }
+
+    /// Returns the number of bytes the bits take up on the heap.
+    pub fn memory_usage(&self) -> usize {
+        self.words.capacity() * mem::size_of::<u64>()
+    }
+}
+
+fn words(len: usize) -> usize {
+    len / WORD_BITS + if len % WORD_BITS == 0 { 0 } else { 1 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BitSet;
+
+    #[test]
+    fn bits_are_set_and_cleared_independently() {
+        let mut bits = BitSet::new(130);
+        assert_eq!((bits.len(), bits.count_ones()), (130, 0));
+
+        for &bit in [0, 63, 64, 129].iter() {
+            bits.set(bit, true);
+        }
+        bits.set(63, false);
+        assert_eq!((0 .. 130).filter(|&bit| bits.get(bit)).collect::<Vec<usize>>(), vec![0, 64, 129]);
+        assert_eq!(bits.count_ones(), 3);
+        assert_eq!(bits.memory_usage(), 3 * 8);
+        assert_eq!(BitSet::new(128).memory_usage(), 2 * 8);
+        assert_eq!(BitSet::memory_usage_of(130), bits.memory_usage());
+    }
+
+    #[test]
+    #[should_panic(expected = "Bit 10 is out of bounds")]
+    fn bits_past_the_end_are_out_of_bounds() {
+        BitSet::new(10).get(10);
+    }
+}
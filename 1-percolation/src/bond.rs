@@ -0,0 +1,213 @@
+use bitset::BitSet;
+use lattice::{Boundary, Lattice, Step};
+use percolation::System;
+use unionfind::{UnionFind, UnionFindKind};
+use wrapping::{Wrapping, WrappingUF};
+
+/// Bond percolation: every site of the lattice is present, and it is the bonds between neighbouring sites which
+/// open. The system percolates when open bonds connect the top row to the bottom row, or on a torus when they form a
+/// cluster which wraps around vertically.
+pub struct BondPercolation {
+    lattice: Lattice,
+    bonds: Vec<(usize, usize)>,
+    // the step from the lower site of each bond to the higher one, only kept on periodic lattices, where it tells
+    // apart the two bonds joining sites both ways around a dimension of size 2
+    steps: Vec<Step>,
+    open: BitSet,
+    open_bonds: usize,
+    // connects sites joined by open bonds, with the top and bottom rows joined to a virtual top and bottom node
+    qu: Box<UnionFind>,
+    // connects sites joined by open bonds only, for cluster sizes which aren't skewed by the virtual nodes
+    clusters: Box<UnionFind>,
+    // tracks clusters wrapping around periodic lattices
+    wrapping: Option<WrappingUF>,
+}
+
+impl BondPercolation {
+    pub fn new(lattice: Lattice) -> BondPercolation {
+        BondPercolation::with_union_find(lattice, UnionFindKind::WeightedQuickUnion)
+    }
+
+    pub fn with_union_find(lattice: Lattice, uf: UnionFindKind) -> BondPercolation {
+        let sites = lattice.site_count();
+        let periodic = lattice.boundary != Boundary::Fixed;
+        let bond_steps = lattice.bond_steps();
+        let bonds = bond_steps.iter().map(|&(p, q, _)| (p, q)).collect::<Vec<(usize, usize)>>();
+        let steps = if periodic { bond_steps.into_iter().map(|(_, _, step)| step).collect() } else { Vec::new() };
+        let mut qu = uf.create(sites + 2);
+        for site in 0 .. sites {
+            if lattice.is_top(site) {
+                qu.union(site, sites);
+            }
+            if lattice.is_bottom(site) {
+                qu.union(site, sites + 1);
+            }
+        }
+        BondPercolation {
+            lattice: lattice,
+            open: BitSet::new(bonds.len()),
+            bonds: bonds,
+            steps: steps,
+            open_bonds: 0,
+            qu: qu,
+            clusters: uf.create(sites),
+            wrapping: if periodic { Some(WrappingUF::new(sites)) } else { None },
+        }
+    }
+
+    pub fn lattice(&self) -> &Lattice {
+        &self.lattice
+    }
+
+    pub fn bond_count(&self) -> usize {
+        self.bonds.len()
+    }
+
+    /// Returns the two sites joined by `bond`.
+    pub fn bond(&self, bond: usize) -> (usize, usize) {
+        self.bonds[bond]
+    }
+
+    pub fn open_bond(&mut self, bond: usize) {
+        if self.open.get(bond) {
+            return;
+        }
+        self.open.set(bond, true);
+        self.open_bonds += 1;
+
+        let (p, q) = self.bonds[bond];
+        self.qu.union(p, q);
+        self.clusters.union(p, q);
+        if let Some(ref mut wrapping) = self.wrapping {
+            wrapping.union(p, q, self.steps[bond]);
+        }
+    }
+
+    pub fn is_bond_open(&self, bond: usize) -> bool {
+        self.open.get(bond)
+    }
+
+    pub fn number_of_open_bonds(&self) -> usize {
+        self.open_bonds
+    }
+
+    /// Returns the number of sites in the cluster containing `site`.
+    pub fn cluster_size_of_site(&self, site: usize) -> usize {
+        self.clusters.component_size(site)
+    }
+
+    pub fn percolates(&self) -> bool {
+        if self.lattice.boundary == Boundary::Torus {
+            self.wrapping().vertically
+        } else {
+            let sites = self.lattice.site_count();
+            self.qu.connected(sites, sites + 1)
+        }
+    }
+
+    /// Returns which directions some cluster wraps around the lattice in; nothing wraps around a fixed boundary.
+    pub fn wrapping(&self) -> Wrapping {
+        self.wrapping.as_ref().map(|wrapping| wrapping.wrapping()).unwrap_or(Wrapping::default())
+    }
+
+    /// Whether the system would percolate if `bond` were open as well, without opening it. As with sites, this isn't
+    /// available on a torus.
+    pub fn would_percolate(&self, bond: usize) -> bool {
+        assert!(self.lattice.boundary != Boundary::Torus, "Percolation can't be looked ahead on a torus");
+        let (p, q) = self.bonds[bond];
+        let (top, bottom) = (self.lattice.site_count(), self.lattice.site_count() + 1);
+        self.percolates() || (
+            (self.qu.connected(p, top) || self.qu.connected(q, top)) &&
+            (self.qu.connected(p, bottom) || self.qu.connected(q, bottom)))
+    }
+}
+
+impl System for BondPercolation {
+    fn element_count(&self) -> usize {
+        self.bond_count()
+    }
+
+    fn open_element(&mut self, element: usize) {
+        self.open_bond(element)
+    }
+
+    fn number_of_open_elements(&self) -> usize {
+        self.number_of_open_bonds()
+    }
+
+    fn percolates(&self) -> bool {
+        BondPercolation::percolates(self)
+    }
+
+    fn would_percolate(&self, element: usize) -> bool {
+        BondPercolation::would_percolate(self, element)
+    }
+
+    fn cluster_size(&self, element: usize) -> usize {
+        self.cluster_size_of_site(self.bonds[element].0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BondPercolation;
+    use lattice::{Boundary, Lattice};
+
+    #[test]
+    fn bond_percolation_needs_a_vertical_chain_of_open_bonds() {
+        let lattice = Lattice::square(3);
+        let mut perc = BondPercolation::new(lattice);
+        let bond_between = |p: usize, q: usize| {
+            lattice.bonds().iter().position(|&bond| bond == (p, q)).unwrap()
+        };
+
+        perc.open_bond(bond_between(1, 4));
+        perc.open_bond(bond_between(4, 5));
+        assert!(!perc.percolates());
+
+        perc.open_bond(bond_between(5, 8));
+        assert!(perc.percolates());
+        assert_eq!(perc.number_of_open_bonds(), 3);
+        assert_eq!(perc.cluster_size_of_site(4), 4);
+        assert_eq!(perc.cluster_size_of_site(0), 1);
+    }
+
+    #[test]
+    fn bond_percolation_reopening_a_bond_does_not_count_twice() {
+        let mut perc = BondPercolation::new(Lattice::square(4));
+        perc.open_bond(0);
+        perc.open_bond(0);
+        assert!(perc.is_bond_open(0));
+        assert!(!perc.is_bond_open(1));
+        assert_eq!(perc.number_of_open_bonds(), 1);
+    }
+
+    #[test]
+    fn bond_percolation_on_a_torus_needs_a_wrapping_cluster() {
+        let lattice = Lattice::square(3).with_boundary(Boundary::Torus);
+        let mut perc = BondPercolation::new(lattice);
+        let bond_between = |p: usize, q: usize| {
+            lattice.bonds().iter().position(|&bond| bond == (p, q)).unwrap()
+        };
+
+        perc.open_bond(bond_between(1, 4));
+        perc.open_bond(bond_between(4, 7));
+        assert!(!perc.percolates());
+
+        perc.open_bond(bond_between(1, 7));
+        assert!(perc.percolates());
+        assert!(!perc.wrapping().horizontally);
+    }
+
+    #[test]
+    fn bond_percolation_wraps_through_both_bonds_of_a_narrow_torus() {
+        let mut perc = BondPercolation::new(Lattice::rectangle(3, 2).with_boundary(Boundary::Torus));
+        // sites 0 and 1 are joined by a bond each way around the two columns
+        assert_eq!((perc.bond(2), perc.bond(3)), ((0, 1), (0, 1)));
+        perc.open_bond(2);
+        assert!(!perc.wrapping().horizontally);
+        perc.open_bond(3);
+        assert!(perc.wrapping().horizontally);
+        assert_eq!(perc.cluster_size_of_site(0), 2);
+    }
+}
@@ -0,0 +1,253 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::iter;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use time;
+use lattice::{Boundary, Lattice};
+use percolation::{self, Model, Simulation};
+use stats::PercolationStats;
+use strategy::{ProbabilityMap, Strategy};
+use unionfind::UnionFindKind;
+
+/// The progress of a run of `times` trials of `sim`. Each trial's random numbers only depend on `seed` and the trial
+/// number, so together with the trials which have finished the seed is all the random state there is to save.
+#[derive(Clone, Debug)]
+pub struct Checkpoint {
+    pub sim: Simulation,
+    pub seed: usize,
+    /// The threshold of each trial which has finished, by trial number.
+    pub results: Vec<Option<f32>>,
+}
+
+impl Checkpoint {
+    pub fn new(sim: &Simulation, times: usize, seed: usize) -> Checkpoint {
+        Checkpoint { sim: sim.clone(), seed: seed, results: iter::repeat(None).take(times).collect() }
+    }
+
+    pub fn times(&self) -> usize {
+        self.results.len()
+    }
+
+    pub fn finished(&self) -> usize {
+        self.results.iter().filter(|result| result.is_some()).count()
+    }
+
+    /// Returns the trials which haven't finished yet, in order.
+    pub fn pending(&self) -> Vec<usize> {
+        (0 .. self.times()).filter(|&trial| self.results[trial].is_none()).collect()
+    }
+
+    /// Returns the statistics of the trials which have finished, in trial order.
+    pub fn stats(&self) -> PercolationStats {
+        PercolationStats::new(self.results.iter().filter_map(|&result| result).collect())
+    }
+
+    /// Writes the checkpoint to `path` by way of a temporary file next to it, so that a crash part way through
+    /// writing leaves the previous checkpoint intact.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let temporary = path.with_extension("tmp");
+        {
+            let mut file = try!(File::create(&temporary));
+            try!(write!(file, "{}", self));
+            try!(file.sync_all());
+        }
+        fs::rename(&temporary, path)
+    }
+}
+
+/// Lists the settings of the run one per line, followed by a `result TRIAL THRESHOLD` line for each finished trial.
+/// Thresholds are written with enough digits to read back exactly. A probability map is saved with a `map` line of
+/// probabilities for each of its rows, so that resuming doesn't depend on its file staying the same.
+impl fmt::Display for Checkpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(writeln!(f, "# percolation checkpoint"));
+        try!(writeln!(f, "lattice {}", self.sim.lattice));
+        try!(writeln!(f, "boundary {}", self.sim.lattice.boundary));
+        try!(writeln!(f, "model {}", self.sim.model));
+        try!(writeln!(f, "union_find {}", self.sim.union_find));
+        try!(writeln!(f, "strategy {}", self.sim.strategy));
+        if let Strategy::Map(ref map) = self.sim.strategy {
+            for row in map.probabilities.chunks(map.cols) {
+                try!(writeln!(f, "map {}", row.iter().map(|p| format!("{:.8e}", p)).collect::<Vec<String>>()
+                    .connect(" ")));
+            }
+        }
+        try!(writeln!(f, "seed {}", self.seed));
+        try!(writeln!(f, "times {}", self.times()));
+        for (trial, result) in self.results.iter().enumerate() {
+            if let Some(result) = *result {
+                try!(writeln!(f, "result {} {:.8e}", trial, result));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Checkpoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Checkpoint, String> {
+        let (mut lattice, mut boundary, mut model, mut union_find) = (None, None, None, None);
+        let (mut strategy, mut seed, mut times) = (None, None, None);
+        // the rows of a probability map, in the format of its file
+        let (mut map, mut map_line) = (String::new(), None);
+        let mut results = Vec::new();
+        for (number, line) in s.lines().enumerate() {
+            let words = line.split(|c: char| c.is_whitespace()).filter(|word| !word.is_empty())
+                .collect::<Vec<&str>>();
+            if words.is_empty() || words[0].starts_with("#") {
+                continue;
+            }
+            let error = |message: String| format!("Line {}: {}", number + 1, message);
+            match (words[0], words.len()) {
+                ("lattice", 2) => lattice = Some(try!(words[1].parse::<Lattice>().map_err(|e| error(e)))),
+                ("boundary", 2) => boundary = Some(try!(words[1].parse::<Boundary>().map_err(|e| error(e)))),
+                ("model", 2) => model = Some(try!(words[1].parse::<Model>().map_err(|e| error(e)))),
+                ("union_find", 2) => union_find = Some(try!(words[1].parse::<UnionFindKind>().map_err(|e| error(e)))),
+                // map file names may have spaces in them
+                ("strategy", _) if words.len() >= 2 =>
+                    strategy = Some((line.trim()["strategy".len()..].trim(), number)),
+                ("map", _) if words.len() >= 2 => {
+                    map_line = map_line.or(Some(number));
+                    map.push_str(&words[1..].connect(" "));
+                    map.push('\n');
+                },
+                ("seed", 2) => seed = Some(try!(words[1].parse::<usize>()
+                    .map_err(|_| error(format!("expected a seed, found '{}'", words[1]))))),
+                ("times", 2) => times = Some(try!(words[1].parse::<usize>()
+                    .map_err(|_| error(format!("expected a number of trials, found '{}'", words[1]))))),
+                ("result", 3) => match (words[1].parse::<usize>(), words[2].parse::<f32>()) {
+                    (Ok(trial), Ok(result)) => results.push((trial, result, number)),
+                    _ => return Err(error(format!("expected a trial and its threshold, found '{}'", line))),
+                },
+                _ => return Err(error(format!("unexpected '{}'", line))),
+            }
+        }
+
+        let strategy = match (strategy, map_line) {
+            (None, None) => None,
+            (Some((strategy, _)), Some(number)) if strategy.starts_with("map:") =>
+                Some(Strategy::Map(Arc::new(try!(ProbabilityMap::parse(&strategy["map:".len()..], &map)
+                    .map_err(|e| format!("The probability map from line {}: {}", number + 1, e)))))),
+            (Some((strategy, number)), None) if strategy.starts_with("map:") =>
+                return Err(format!("Line {}: the checkpoint doesn't include the probability map", number + 1)),
+            (Some((strategy, number)), None) =>
+                Some(try!(strategy.parse::<Strategy>().map_err(|e| format!("Line {}: {}", number + 1, e)))),
+            (_, Some(number)) => return Err(format!("Line {}: only a map strategy has a probability map", number + 1)),
+        };
+
+        let mut checkpoint = match (lattice, boundary, model, union_find, strategy, seed, times) {
+            (Some(lattice), Some(boundary), Some(model), Some(union_find), Some(strategy), Some(seed), Some(times)) => {
+                let sim = Simulation { lattice: lattice.with_boundary(boundary), model: model, union_find: union_find,
+                    strategy: strategy };
+                Checkpoint::new(&sim, times, seed)
+            },
+            _ => return Err("A checkpoint needs a lattice, boundary, model, union_find, strategy, seed and times"
+                .to_string()),
+        };
+        for (trial, result, number) in results {
+            if trial >= checkpoint.times() {
+                return Err(format!("Line {}: trial {} is not one of the {} trials", number + 1, trial,
+                    checkpoint.times()));
+            }
+            checkpoint.results[trial] = Some(result);
+        }
+        Ok(checkpoint)
+    }
+}
+
+/// Runs the trials of `checkpoint` which haven't finished yet over at most `jobs` threads, and hands the checkpoint
+/// to `save` whenever `interval_s` seconds have passed since it was last saved, as well as once the trials stop. The
+/// trials are the same ones `percolation::simulate_multiple` runs, so resuming from a checkpoint gives the same
+/// results as an uninterrupted run. Stops at the first error from `save`.
+pub fn run_from<F>(checkpoint: &mut Checkpoint, jobs: u32, interval_s: f64, mut save: F) -> io::Result<()>
+        where F: FnMut(&Checkpoint) -> io::Result<()> {
+    let (sim, seed, pending) = (checkpoint.sim.clone(), checkpoint.seed, checkpoint.pending());
+    let mut error = None;
+    let mut last_save = time::precise_time_s();
+    percolation::simulate_trials_while(&sim, pending, jobs, seed, |trial, result| {
+        checkpoint.results[trial] = Some(result);
+        let now = time::precise_time_s();
+        if now - last_save >= interval_s {
+            last_save = now;
+            if let Err(e) = save(checkpoint) {
+                error = Some(e);
+                return false;
+            }
+        }
+        true
+    });
+    match error {
+        Some(e) => Err(e),
+        None => save(checkpoint),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::sync::Arc;
+    use super::{Checkpoint, run_from};
+    use lattice::{Boundary, Lattice};
+    use percolation::{Model, Simulation, simulate_multiple};
+    use strategy::{ProbabilityMap, Strategy};
+
+    fn sim() -> Simulation {
+        Simulation { model: Model::Bond, ..Simulation::on(Lattice::rectangle(6, 8).with_boundary(Boundary::Cylinder)) }
+    }
+
+    #[test]
+    fn checkpoints_round_trip_through_text() {
+        let mut checkpoint = Checkpoint::new(&sim(), 4, 99);
+        checkpoint.results[1] = Some(0.59375);
+        checkpoint.results[3] = Some(1.0 / 3.0);
+        let parsed = checkpoint.to_string().parse::<Checkpoint>().unwrap();
+
+        assert_eq!(parsed.sim.lattice, sim().lattice);
+        assert_eq!((parsed.sim.model, parsed.sim.union_find, parsed.seed), (Model::Bond, sim().union_find, 99));
+        assert_eq!(parsed.results, checkpoint.results);
+        assert_eq!(parsed.pending(), vec![0, 2]);
+        assert_eq!(parsed.sim.strategy, Strategy::Uniform);
+        let adversarial = Checkpoint::new(&Simulation { strategy: Strategy::Adversarial, ..sim() }, 4, 99);
+        assert_eq!(adversarial.to_string().parse::<Checkpoint>().unwrap().sim.strategy, Strategy::Adversarial);
+        assert!(checkpoint.to_string().replace("strategy uniform\n", "").parse::<Checkpoint>().is_err());
+
+        // a map is saved whole, so resuming doesn't need its file
+        let map = ProbabilityMap::parse("no-such-map.txt", "0.5 1 0.1\n1 0.3 0\n").unwrap();
+        let mapped = Checkpoint::new(&Simulation { strategy: Strategy::Map(Arc::new(map)),
+            ..Simulation::on(Lattice::rectangle(2, 3)) }, 4, 99);
+        assert_eq!(mapped.to_string().parse::<Checkpoint>().unwrap().sim.strategy, mapped.sim.strategy);
+        let without_map = mapped.to_string().lines().filter(|line| !line.starts_with("map "))
+            .collect::<Vec<&str>>().connect("\n");
+        assert!(without_map.parse::<Checkpoint>().is_err());
+
+        assert!("lattice 4\nseed 1\n".parse::<Checkpoint>().is_err());
+        assert!(checkpoint.to_string().replace("result 3", "result 4").parse::<Checkpoint>().is_err());
+    }
+
+    #[test]
+    fn resumed_runs_match_uninterrupted_ones() {
+        let mut checkpoint = Checkpoint::new(&sim(), 20, 7);
+        let uninterrupted = simulate_multiple(&sim(), 20, 1, 7);
+        // pretend a run got through some of the trials before it stopped
+        for &trial in [0, 1, 2, 5, 11].iter() {
+            checkpoint.results[trial] = Some(uninterrupted.results()[trial]);
+        }
+
+        let mut saves = 0;
+        run_from(&mut checkpoint, 3, 0.0, |saved| {
+            saves += 1;
+            assert!(saved.finished() >= 5);
+            Ok(())
+        }).unwrap();
+        assert_eq!(checkpoint.finished(), 20);
+        assert_eq!(checkpoint.stats().results(), uninterrupted.results());
+        assert!(saves >= 2);
+
+        let unwritable = Path::new("no-such-directory/checkpoint.txt");
+        assert!(run_from(&mut Checkpoint::new(&sim(), 5, 7), 1, 0.0, |saved| saved.save(unwritable)).is_err());
+    }
+}
@@ -0,0 +1,185 @@
+use std::f64;
+use std::iter;
+use percolation::{self, Percolation, Simulation};
+use rand::Rng;
+
+/// Statistics of the open clusters of a system at one moment.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClusterStats {
+    /// Entry `s` is the number of clusters of `s` sites, so entry 0 is always 0.
+    pub distribution: Vec<usize>,
+    /// Number of sites in the largest cluster, or 0 if there are no clusters.
+    pub largest: usize,
+    /// Mean size of the cluster containing a random open site, over the clusters other than (one of) the largest:
+    /// S = Σ s² n_s / Σ s n_s, which diverges at the threshold. NaN with fewer than two clusters.
+    pub mean_excluding_largest: f64,
+    pub count: usize,
+}
+
+impl ClusterStats {
+    pub fn from_sizes(sizes: &[usize]) -> ClusterStats {
+        let largest = sizes.iter().cloned().max().unwrap_or(0);
+        let mut distribution: Vec<usize> = iter::repeat(0).take(largest + 1).collect();
+        for &size in sizes {
+            distribution[size] += 1;
+        }
+        // sums of s and s² over the clusters, less the largest
+        let sites = sizes.iter().fold(0, |sum, &size| sum + size) - largest;
+        let squares = sizes.iter().fold(0, |sum, &size| sum + size * size) - largest * largest;
+        ClusterStats {
+            distribution: distribution,
+            largest: largest,
+            mean_excluding_largest: if sizes.len() < 2 {
+                f64::NAN
+            } else {
+                squares as f64 / sites as f64
+            },
+            count: sizes.len(),
+        }
+    }
+}
+
+/// Cluster statistics at one open site fraction, averaged over trials.
+#[derive(Clone, Debug)]
+pub struct ClusterSample {
+    pub fraction: f32,
+    pub trials: usize,
+    // totals over the trials, averaged on the way out
+    distribution: Vec<usize>,
+    largest: usize,
+    mean_excluding_largest: f64,
+    // trials which had a mean excluding the largest cluster at all
+    trials_with_mean: usize,
+    count: usize,
+}
+
+impl ClusterSample {
+    pub fn new(fraction: f32) -> ClusterSample {
+        ClusterSample {
+            fraction: fraction,
+            trials: 0,
+            distribution: Vec::new(),
+            largest: 0,
+            mean_excluding_largest: 0.0,
+            trials_with_mean: 0,
+            count: 0,
+        }
+    }
+
+    pub fn add(&mut self, stats: &ClusterStats) {
+        self.trials += 1;
+        if self.distribution.len() < stats.distribution.len() {
+            let missing = stats.distribution.len() - self.distribution.len();
+            self.distribution.extend(iter::repeat(0).take(missing));
+        }
+        for (s, &clusters) in stats.distribution.iter().enumerate() {
+            self.distribution[s] += clusters;
+        }
+        self.largest += stats.largest;
+        if stats.mean_excluding_largest.is_finite() {
+            self.mean_excluding_largest += stats.mean_excluding_largest;
+            self.trials_with_mean += 1;
+        }
+        self.count += stats.count;
+    }
+
+    /// Entry `s` is the mean number of clusters of `s` sites.
+    pub fn distribution(&self) -> Vec<f64> {
+        self.distribution.iter().map(|&clusters| clusters as f64 / self.trials as f64).collect()
+    }
+
+    pub fn largest(&self) -> f64 {
+        self.largest as f64 / self.trials as f64
+    }
+
+    /// Mean over the trials which had at least two clusters.
+    pub fn mean_excluding_largest(&self) -> f64 {
+        self.mean_excluding_largest / self.trials_with_mean as f64
+    }
+
+    pub fn count(&self) -> f64 {
+        self.count as f64 / self.trials as f64
+    }
+}
+
+/// Opens the sites of a site percolation system in the order of its strategy, and returns its cluster statistics once
+/// each of `fractions` of the sites are open. The fractions must be in increasing order.
+pub fn sample_clusters<R: Rng>(sim: &Simulation, fractions: &[f32], rng: &mut R) -> Vec<ClusterStats> {
+    let mut perc = Percolation::with_lattice(sim.lattice, sim.union_find);
+    let sites = sim.lattice.site_count();
+    let mut opener = sim.opener(&perc, rng);
+
+    let mut samples = Vec::with_capacity(fractions.len());
+    for &fraction in fractions {
+        let target = (fraction * sites as f32).round() as usize;
+        while perc.number_of_open_sites() < target {
+            let site = opener.next(&perc).unwrap(); // safe: no more than every site is ever opened
+            perc.open_site(site);
+        }
+        samples.push(perc.cluster_stats());
+    }
+    samples
+}
+
+/// Samples the clusters of `times` trials of `sim` spread over at most `jobs` threads, and averages them for each
+/// fraction. As with `percolation::simulate_multiple`, the result only depends on `seed`.
+pub fn simulate_clusters(sim: &Simulation, fractions: &[f32], times: usize, jobs: u32, seed: usize)
+        -> Vec<ClusterSample> {
+    let sim = sim.clone();
+    let mut samples = fractions.iter().map(|&fraction| ClusterSample::new(fraction)).collect::<Vec<ClusterSample>>();
+    let fractions = fractions.to_vec();
+    percolation::run_trials(times, jobs, move |trial| {
+        sample_clusters(&sim, &fractions[..], &mut percolation::trial_rng(seed, trial))
+    }, |_, stats| {
+        for (sample, stats) in samples.iter_mut().zip(stats.iter()) {
+            sample.add(stats);
+        }
+    });
+    samples
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ClusterStats, simulate_clusters};
+    use percolation::{Percolation, Simulation};
+
+    #[test]
+    fn cluster_stats_summarise_sizes() {
+        let stats = ClusterStats::from_sizes(&[3, 1, 1, 7]);
+
+        assert_eq!(stats.distribution, vec![0, 2, 0, 1, 0, 0, 0, 1]);
+        assert_eq!(stats.largest, 7);
+        // (3² + 1² + 1²) / (3 + 1 + 1), not the plain mean of 5 / 3
+        assert_eq!(stats.mean_excluding_largest, 11.0 / 5.0);
+        assert_eq!(stats.count, 4);
+        assert!(ClusterStats::from_sizes(&[2]).mean_excluding_largest.is_nan());
+    }
+
+    #[test]
+    fn percolation_reports_its_open_clusters() {
+        let mut perc = Percolation::new(4);
+        assert_eq!(perc.cluster_stats().count, 0);
+
+        for &(i, j) in [(1, 1), (1, 2), (2, 2), (4, 4), (3, 4), (1, 4)].iter() {
+            perc.open(i, j);
+        }
+        let stats = perc.cluster_stats();
+        assert_eq!(stats.distribution, vec![0, 1, 1, 1]);
+        assert_eq!(stats.largest, 3);
+        assert_eq!(stats.mean_excluding_largest, 5.0 / 3.0);
+        assert_eq!(stats.count, 3);
+    }
+
+    #[test]
+    fn samples_average_over_trials() {
+        let samples = simulate_clusters(&Simulation::new(10), &[0.0, 0.3, 1.0], 4, 2, 8);
+
+        assert_eq!(samples.len(), 3);
+        assert_eq!(samples[0].count(), 0.0);
+        assert!(samples[1].count() >= 1.0);
+        assert_eq!(samples[2].count(), 1.0);
+        assert_eq!(samples[2].largest(), 100.0);
+        assert_eq!(samples[2].distribution()[100], 1.0);
+        assert_eq!(simulate_clusters(&Simulation::new(10), &[0.3], 4, 1, 8)[0].count(), samples[1].count());
+    }
+}
@@ -13,6 +13,13 @@ impl AsUsizeConverter for u32 {
     }
 }
 
+impl AsUsizeConverter for u64 {
+    #[inline(always)]
+    fn as_usize(&self) -> usize {
+        *self as usize
+    }
+}
+
 pub trait TryU32Converter {
     /// Converts the value of `self` to an `u32` or panics if it fails
     #[inline(always)]
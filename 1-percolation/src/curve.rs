@@ -0,0 +1,149 @@
+use std::iter;
+use percolation::{self, Model, Simulation};
+use rand::Rng;
+
+/// How a single trial evolved as its elements were opened one at a time in the order of its strategy: entry `k` is the
+/// state after `k` elements were opened, as (whether the system percolates, number of sites in the largest cluster).
+pub fn trace<R: Rng>(sim: &Simulation, rng: &mut R) -> Vec<(bool, usize)> {
+    let mut system = sim.model.create(sim.lattice, sim.union_find);
+    let elements = system.element_count();
+    let mut opener = sim.opener(&*system, rng);
+
+    let mut largest = largest_cluster_before_opening(sim.model);
+    let mut trace = Vec::with_capacity(elements + 1);
+    trace.push((system.percolates(), largest));
+    while let Some(element) = opener.next(&*system) {
+        system.open_element(element);
+        // only the cluster which the element just joined can have grown
+        let size = system.cluster_size(element);
+        if size > largest {
+            largest = size;
+        }
+        trace.push((system.percolates(), largest));
+    }
+    trace
+}
+
+/// Returns the size of the largest cluster before any element opens: the site models have no open sites to make up
+/// clusters yet, while in bond percolation every site is already present as a cluster of its own.
+fn largest_cluster_before_opening(model: Model) -> usize {
+    match model {
+        Model::Site | Model::Directed(_) => 0,
+        Model::Bond => 1,
+    }
+}
+
+/// The percolation probability and largest cluster averaged over trials, as a function of the number of elements
+/// opened. Since every trial opens every element, one pass gives the whole curve rather than a single threshold.
+/// Each point is at an exact number `k` of open elements, not at a probability p of each element being open; curves
+/// in p would take a binomial convolution over `k`.
+#[derive(Debug)]
+pub struct PercolationCurve {
+    sites: usize,
+    trials: usize,
+    // number of trials which percolated after k elements were opened
+    percolating: Vec<usize>,
+    // sum over all trials of the size of the largest cluster after k elements were opened
+    largest_cluster_total: Vec<usize>,
+}
+
+impl PercolationCurve {
+    pub fn new(sim: &Simulation) -> PercolationCurve {
+        let points = sim.model.element_count(&sim.lattice) + 1;
+        PercolationCurve {
+            sites: sim.lattice.site_count(),
+            trials: 0,
+            percolating: iter::repeat(0).take(points).collect(),
+            largest_cluster_total: iter::repeat(0).take(points).collect(),
+        }
+    }
+
+    pub fn add(&mut self, trace: &[(bool, usize)]) {
+        assert_eq!(trace.len(), self.percolating.len());
+        for (k, &(percolates, largest)) in trace.iter().enumerate() {
+            if percolates {
+                self.percolating[k] += 1;
+            }
+            self.largest_cluster_total[k] += largest;
+        }
+        self.trials += 1;
+    }
+
+    pub fn trials(&self) -> usize {
+        self.trials
+    }
+
+    /// Number of points on the curve: one more than the number of elements, for the state before any opened.
+    pub fn len(&self) -> usize {
+        self.percolating.len()
+    }
+
+    /// Fraction of the elements which are open at the `k`th point, `k` over the number of elements.
+    pub fn opened_fraction(&self, k: usize) -> f32 {
+        k as f32 / (self.len() - 1) as f32
+    }
+
+    /// Fraction of trials which percolated with `k` elements open.
+    pub fn percolation_probability(&self, k: usize) -> f32 {
+        self.percolating[k] as f32 / self.trials as f32
+    }
+
+    /// Mean fraction of the sites which are in the largest cluster with `k` elements open.
+    pub fn largest_cluster_fraction(&self, k: usize) -> f32 {
+        self.largest_cluster_total[k] as f32 / (self.trials * self.sites) as f32
+    }
+}
+
+/// Traces `times` trials of `sim` spread over at most `jobs` threads, and averages them into a curve. As with
+/// `percolation::simulate_multiple`, the curve only depends on `seed`.
+pub fn simulate_curve(sim: &Simulation, times: usize, jobs: u32, seed: usize) -> PercolationCurve {
+    let sim = sim.clone();
+    let mut curve = PercolationCurve::new(&sim);
+    percolation::run_trials(times, jobs, move |trial| trace(&sim, &mut percolation::trial_rng(seed, trial)),
+            |_, trace| curve.add(&trace[..]));
+    curve
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{simulate_curve, trace};
+    use percolation::{self, Model, Simulation};
+
+    #[test]
+    fn trace_ends_with_everything_open_and_connected() {
+        let sim = Simulation::new(6);
+        let trace = trace(&sim, &mut percolation::trial_rng(1, 0));
+
+        assert_eq!(trace.len(), 37);
+        assert_eq!(trace[0], (false, 0));
+        assert_eq!(trace[36], (true, 36));
+        // once percolating, a site system keeps percolating, and clusters never shrink
+        for window in trace.windows(2) {
+            assert!(!window[0].0 || window[1].0);
+            assert!(window[0].1 <= window[1].1);
+        }
+    }
+
+    #[test]
+    fn bond_trace_starts_with_single_site_clusters() {
+        let sim = Simulation { model: Model::Bond, ..Simulation::new(4) };
+        let trace = trace(&sim, &mut percolation::trial_rng(1, 0));
+
+        assert_eq!(trace.len(), 2 * 4 * 3 + 1);
+        assert_eq!(trace[0], (false, 1));
+        assert_eq!(trace[trace.len() - 1], (true, 16));
+    }
+
+    #[test]
+    fn curve_averages_trials() {
+        let curve = simulate_curve(&Simulation::new(5), 6, 3, 9);
+
+        assert_eq!(curve.trials(), 6);
+        assert_eq!(curve.len(), 26);
+        assert_eq!(curve.percolation_probability(0), 0.0);
+        assert_eq!(curve.percolation_probability(25), 1.0);
+        assert_eq!(curve.largest_cluster_fraction(25), 1.0);
+        assert_eq!(curve.opened_fraction(5), 0.2);
+        assert_eq!(simulate_curve(&Simulation::new(5), 6, 1, 9).percolating, curve.percolating);
+    }
+}
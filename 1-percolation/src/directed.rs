@@ -0,0 +1,308 @@
+use std::cell::RefCell;
+use bitset::BitSet;
+use lattice::{Boundary, Lattice};
+use percolation::System;
+use unionfind::{UnionFind, UnionFindKind};
+
+/// Which sites a site can be reached from in directed percolation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Orientation {
+    /// Only from the site directly above.
+    Square,
+    /// From the sites diagonally above, one column (or in 3D one layer) to either side, as on a square lattice tilted
+    /// by 45 degrees so that its bonds point down and to the sides.
+    Tilted,
+}
+
+/// Directed site percolation: flow only goes downward, so an open site is wet (reached from the top row) if it is in
+/// the top row or one of the open sites above it is wet. The system percolates once a site in the bottom row is wet.
+/// Opening a site propagates wetness down through the open sites below it, so each site is wetted at most once.
+///
+/// A torus has no top row to flow down from, so directed percolation is only available on fixed and cylindrical
+/// boundaries, where the diagonals of the tilted orientation wrap around with the columns.
+pub struct DirectedPercolation {
+    lattice: Lattice,
+    orientation: Orientation,
+    open: BitSet,
+    wet: BitSet,
+    open_sites: usize,
+    percolates: bool,
+    // connects open sites which can reach each other in either direction, for cluster sizes
+    clusters: Box<UnionFind>,
+    // sites visited by `would_percolate`, kept between calls so that it only has to clear the ones it visited
+    visited: RefCell<BitSet>,
+}
+
+impl DirectedPercolation {
+    pub fn new(lattice: Lattice, orientation: Orientation) -> DirectedPercolation {
+        DirectedPercolation::with_union_find(lattice, orientation, UnionFindKind::WeightedQuickUnion)
+    }
+
+    pub fn with_union_find(lattice: Lattice, orientation: Orientation, uf: UnionFindKind) -> DirectedPercolation {
+        assert!(lattice.boundary != Boundary::Torus, "Directed percolation needs a top row to flow down from");
+        let sites = lattice.site_count();
+        DirectedPercolation {
+            lattice: lattice,
+            orientation: orientation,
+            open: BitSet::new(sites),
+            wet: BitSet::new(sites),
+            open_sites: 0,
+            percolates: false,
+            clusters: uf.create(sites),
+            visited: RefCell::new(BitSet::new(sites)),
+        }
+    }
+
+    pub fn lattice(&self) -> &Lattice {
+        &self.lattice
+    }
+
+    pub fn orientation(&self) -> Orientation {
+        self.orientation
+    }
+
+    /// Returns the sites in the row above `site` which it can be reached from.
+    pub fn upstream(&self, site: usize) -> Vec<usize> {
+        self.adjacent(site, -1)
+    }
+
+    /// Returns the sites in the row below `site` which can be reached from it.
+    pub fn downstream(&self, site: usize) -> Vec<usize> {
+        self.adjacent(site, 1)
+    }
+
+    fn adjacent(&self, site: usize, delta: isize) -> Vec<usize> {
+        let (row, col, layer) = self.lattice.coordinates(site);
+        let periodic = self.lattice.periodic();
+        let sizes = [self.lattice.rows, self.lattice.cols, self.lattice.layers];
+        // moves `at` by `step` along `axis`, wrapping around periodic axes
+        let moved = |at: [usize; 3], axis: usize, step: isize| {
+            let position = at[axis] as isize + step;
+            let mut at = at;
+            at[axis] = if position >= 0 && position < sizes[axis] as isize {
+                position as usize
+            } else if periodic[axis] {
+                ((position + sizes[axis] as isize) % sizes[axis] as isize) as usize
+            } else {
+                return None;
+            };
+            Some(at)
+        };
+
+        // the row above or below never wraps, as directed percolation doesn't run on a torus
+        let next_row = match moved([row, col, layer], 0, delta) {
+            Some(at) => at,
+            None => return Vec::new(),
+        };
+        match self.orientation {
+            Orientation::Square => vec![self.lattice.index(next_row[0], next_row[1], next_row[2])],
+            Orientation::Tilted => {
+                let mut sites = Vec::with_capacity(4);
+                // an axis the lattice doesn't extend along would only wrap back onto the same column or layer
+                for axis in (1 .. 3).filter(|&axis| sizes[axis] > 1) {
+                    for &side in [-1isize, 1].iter() {
+                        if let Some(at) = moved(next_row, axis, side) {
+                            let diagonal = self.lattice.index(at[0], at[1], at[2]);
+                            // narrow cylinders can wrap both diagonals onto the same site
+                            if !sites.contains(&diagonal) {
+                                sites.push(diagonal);
+                            }
+                        }
+                    }
+                }
+                sites
+            },
+        }
+    }
+
+    pub fn open_site(&mut self, site: usize) {
+        if self.open.get(site) {
+            return;
+        }
+        self.open.set(site, true);
+        self.open_sites += 1;
+
+        let upstream = self.upstream(site);
+        for neighbour in upstream.iter().cloned().chain(self.downstream(site).into_iter()) {
+            if self.open.get(neighbour) {
+                self.clusters.union(site, neighbour);
+            }
+        }
+        if self.lattice.is_top(site) || upstream.iter().any(|&above| self.wet.get(above)) {
+            self.wet_from(site);
+        }
+    }
+
+    /// Wets `site`, and every open site below it which it reaches and which isn't wet yet.
+    fn wet_from(&mut self, site: usize) {
+        self.wet.set(site, true);
+        let mut to_visit = vec![site];
+        while let Some(site) = to_visit.pop() {
+            if self.lattice.is_bottom(site) {
+                self.percolates = true;
+            }
+            for below in self.downstream(site) {
+                if self.open.get(below) && !self.wet.get(below) {
+                    self.wet.set(below, true);
+                    to_visit.push(below);
+                }
+            }
+        }
+    }
+
+    pub fn is_open_site(&self, site: usize) -> bool {
+        self.open.get(site)
+    }
+
+    /// Whether `site` is open and reached from the top row.
+    pub fn is_wet_site(&self, site: usize) -> bool {
+        self.wet.get(site)
+    }
+
+    pub fn number_of_open_sites(&self) -> usize {
+        self.open_sites
+    }
+
+    /// Returns the number of sites in the cluster containing `site`, counting sites joined in either direction, or 0
+    /// if `site` isn't open.
+    pub fn cluster_size_of_site(&self, site: usize) -> usize {
+        if self.open.get(site) { self.clusters.component_size(site) } else { 0 }
+    }
+
+    pub fn percolates(&self) -> bool {
+        self.percolates
+    }
+
+    /// Whether the system would percolate if `site` were open as well, without opening it. Unlike the undirected
+    /// models this has to follow the open sites downstream of `site` which aren't wet yet, so it takes time
+    /// proportional to their number.
+    pub fn would_percolate(&self, site: usize) -> bool {
+        if self.percolates || self.open.get(site) {
+            return self.percolates;
+        }
+        if !self.lattice.is_top(site) && !self.upstream(site).iter().any(|&above| self.wet.get(above)) {
+            return false;
+        }
+        let mut visited = self.visited.borrow_mut();
+        let (mut to_visit, mut seen) = (vec![site], vec![site]);
+        visited.set(site, true);
+        let mut reaches_bottom = false;
+        while let Some(site) = to_visit.pop() {
+            if self.lattice.is_bottom(site) {
+                reaches_bottom = true;
+                break;
+            }
+            // a wet site doesn't reach the bottom, or the system would already percolate
+            for below in self.downstream(site) {
+                if self.open.get(below) && !self.wet.get(below) && !visited.get(below) {
+                    visited.set(below, true);
+                    seen.push(below);
+                    to_visit.push(below);
+                }
+            }
+        }
+        for site in seen {
+            visited.set(site, false);
+        }
+        reaches_bottom
+    }
+}
+
+impl System for DirectedPercolation {
+    fn element_count(&self) -> usize {
+        self.lattice.site_count()
+    }
+
+    fn open_element(&mut self, element: usize) {
+        self.open_site(element)
+    }
+
+    fn number_of_open_elements(&self) -> usize {
+        self.number_of_open_sites()
+    }
+
+    fn percolates(&self) -> bool {
+        DirectedPercolation::percolates(self)
+    }
+
+    fn would_percolate(&self, element: usize) -> bool {
+        DirectedPercolation::would_percolate(self, element)
+    }
+
+    fn cluster_size(&self, element: usize) -> usize {
+        self.cluster_size_of_site(element)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DirectedPercolation, Orientation};
+    use lattice::{Boundary, Lattice};
+    use percolation::{Model, Percolation, Simulation, simulate_multiple};
+    use unionfind::UnionFindKind;
+
+    #[test]
+    fn flow_only_goes_downward() {
+        let lattice = Lattice::square(3);
+        let mut perc = Percolation::with_lattice(lattice, UnionFindKind::WeightedQuickUnion);
+        let mut directed = DirectedPercolation::new(lattice, Orientation::Square);
+        // down the first column, across the middle row and down the second column
+        for &site in [0, 3, 4, 7].iter() {
+            perc.open_site(site);
+            directed.open_site(site);
+        }
+        assert!(perc.percolates());
+        assert!(!directed.percolates());
+        assert!(directed.is_wet_site(3) && !directed.is_wet_site(4));
+        assert_eq!(directed.cluster_size_of_site(7), 2);
+        assert_eq!(directed.cluster_size_of_site(8), 0);
+        assert_eq!((directed.upstream(4), directed.downstream(4)), (vec![1], vec![7]));
+
+        // opening the top of the second column wets the rest of it
+        assert!(directed.would_percolate(1));
+        assert!(!directed.would_percolate(2));
+        directed.open_site(1);
+        assert!(directed.percolates());
+        assert_eq!(directed.number_of_open_sites(), 5);
+    }
+
+    #[test]
+    fn tilted_flow_goes_diagonally() {
+        let mut directed = DirectedPercolation::new(Lattice::rectangle(3, 3), Orientation::Tilted);
+        directed.open_site(4);
+        directed.open_site(6);
+        assert!(!directed.would_percolate(3));
+        assert!(!directed.percolates());
+
+        directed.open_site(2);
+        assert!(directed.percolates());
+        assert!(!directed.is_wet_site(3) && directed.upstream(6) == vec![4]);
+
+        // the diagonals wrap around a cylinder, but not a fixed boundary
+        let mut cylinder = DirectedPercolation::new(Lattice::rectangle(2, 3).with_boundary(Boundary::Cylinder),
+            Orientation::Tilted);
+        cylinder.open_site(0);
+        cylinder.open_site(5);
+        assert!(cylinder.percolates());
+        // the single layer of a cylinder doesn't wrap onto the site straight above
+        assert_eq!(cylinder.upstream(5), vec![1, 0]);
+        assert_eq!(DirectedPercolation::new(Lattice::rectangle(2, 3), Orientation::Tilted).upstream(5), vec![1]);
+    }
+
+    #[test]
+    fn directed_thresholds_are_higher_than_isotropic_ones() {
+        let isotropic = simulate_multiple(&Simulation::new(16), 20, 1, 3);
+        let directed = simulate_multiple(&Simulation { model: Model::Directed(Orientation::Tilted),
+            ..Simulation::new(16) }, 20, 1, 3);
+        assert_eq!(directed.trials(), 20);
+        assert!(directed.mean() > isotropic.mean());
+        assert_eq!("directed-tilted".parse::<Model>(), Ok(Model::Directed(Orientation::Tilted)));
+        assert_eq!(Model::Directed(Orientation::Square).to_string(), "directed");
+    }
+
+    #[test]
+    #[should_panic(expected = "needs a top row")]
+    fn a_torus_has_nowhere_to_flow_from() {
+        DirectedPercolation::new(Lattice::square(3).with_boundary(Boundary::Torus), Orientation::Square);
+    }
+}
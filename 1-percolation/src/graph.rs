@@ -0,0 +1,377 @@
+use std::iter;
+use std::str::FromStr;
+use std::sync::Arc;
+use percolation::{self, Model, System};
+use stats::PercolationStats;
+use unionfind::{UnionFind, UnionFindKind};
+
+/// An undirected graph with nodes numbered from 0, and the nodes which percolation has to connect: the system
+/// percolates once any source is connected to any sink.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Graph {
+    pub nodes: usize,
+    pub edges: Vec<(usize, usize)>,
+    pub sources: Vec<usize>,
+    pub sinks: Vec<usize>,
+    // whether each node is a source or a sink, so that trials don't search the lists for every node
+    is_source: Vec<bool>,
+    is_sink: Vec<bool>,
+}
+
+impl Graph {
+    /// Checks that the edges, sources and sinks only refer to the given nodes, and that the sources are connected to
+    /// the sinks once every node and edge is open (otherwise no trial could ever percolate).
+    pub fn new(nodes: usize, edges: Vec<(usize, usize)>, sources: Vec<usize>, sinks: Vec<usize>)
+            -> Result<Graph, String> {
+        if let Some(&(p, q)) = edges.iter().find(|&&(p, q)| p >= nodes || q >= nodes) {
+            return Err(format!("Edge {} {} refers to a node outside the graph of {} nodes", p, q, nodes));
+        }
+        if let Some(&node) = sources.iter().chain(sinks.iter()).find(|&&node| node >= nodes) {
+            return Err(format!("Node {} is outside the graph of {} nodes", node, nodes));
+        }
+        if sources.is_empty() || sinks.is_empty() {
+            return Err("A graph needs at least one source and one sink".to_string());
+        }
+        let mark = |terminals: &[usize]| {
+            let mut marked: Vec<bool> = iter::repeat(false).take(nodes).collect();
+            for &node in terminals {
+                marked[node] = true;
+            }
+            marked
+        };
+        let (is_source, is_sink) = (mark(&sources), mark(&sinks));
+        let graph = Graph { nodes: nodes, edges: edges, sources: sources, sinks: sinks, is_source: is_source,
+            is_sink: is_sink };
+        let mut everything = GraphPercolation::new(Arc::new(graph.clone()), Model::Bond);
+        for edge in 0 .. graph.edges.len() {
+            everything.open_edge(edge);
+        }
+        if everything.percolates() {
+            Ok(graph)
+        } else {
+            Err("The sources are not connected to the sinks even with every node and edge open".to_string())
+        }
+    }
+
+    pub fn is_source(&self, node: usize) -> bool {
+        self.is_source[node]
+    }
+
+    pub fn is_sink(&self, node: usize) -> bool {
+        self.is_sink[node]
+    }
+
+    /// Parses an edge list like `from_str`, adding `sources` and `sinks` to the ones it lists. Errors refer to the
+    /// lines of `s` as they are.
+    pub fn parse_with_terminals(s: &str, sources: &[usize], sinks: &[usize]) -> Result<Graph, String> {
+        let (mut edges, mut all_sources, mut all_sinks) = (Vec::new(), Vec::new(), Vec::new());
+        for (number, line) in s.lines().enumerate() {
+            let words = line.split(|c: char| c.is_whitespace()).filter(|word| !word.is_empty())
+                .collect::<Vec<&str>>();
+            if words.is_empty() || words[0].starts_with("#") || words[0].starts_with("%") {
+                continue;
+            }
+            let nodes = |words: &[&str]| -> Result<Vec<usize>, String> {
+                words.iter().map(|word| {
+                    word.parse::<usize>().map_err(|_| format!("Line {}: expected a node number, found '{}'", number + 1, word))
+                }).collect()
+            };
+            match words[0] {
+                "source" => all_sources.push_all(&try!(nodes(&words[1..]))),
+                "sink" => all_sinks.push_all(&try!(nodes(&words[1..]))),
+                _ if words.len() >= 2 => {
+                    let edge = try!(nodes(&words[..2]));
+                    edges.push((edge[0], edge[1]));
+                },
+                _ => return Err(format!("Line {}: expected an edge, found '{}'", number + 1, line)),
+            }
+        }
+        all_sources.push_all(sources);
+        all_sinks.push_all(sinks);
+        // renumber the nodes which appear in order from 0, so that gaps in the numbering don't add isolated nodes
+        let mut ids = edges.iter().flat_map(|&(p, q)| vec![p, q].into_iter())
+            .chain(all_sources.iter().cloned()).chain(all_sinks.iter().cloned())
+            .collect::<Vec<usize>>();
+        ids.sort();
+        ids.dedup();
+        let node = |id: usize| ids.binary_search(&id).unwrap(); // safe: every id was collected above
+        let edges = edges.iter().map(|&(p, q)| (node(p), node(q))).collect();
+        let all_sources = all_sources.iter().map(|&id| node(id)).collect();
+        let all_sinks = all_sinks.iter().map(|&id| node(id)).collect();
+        Graph::new(ids.len(), edges, all_sources, all_sinks)
+    }
+
+    /// Returns the nodes joined to each node by an edge.
+    pub fn adjacency(&self) -> Vec<Vec<usize>> {
+        let mut adjacent: Vec<Vec<usize>> = iter::repeat(Vec::new()).take(self.nodes).collect();
+        for &(p, q) in self.edges.iter() {
+            adjacent[p].push(q);
+            adjacent[q].push(p);
+        }
+        adjacent
+    }
+}
+
+/// Parses an edge list with one edge per line as two node numbers, followed by anything (such as a weight) which is
+/// ignored. Lines `source N...` and `sink N...` list sources and sinks, and lines starting with `#` or `%` are
+/// comments. Only the nodes which appear are part of the graph, numbered from 0 in the order of their numbers in the
+/// file, so that gaps in the numbering don't add isolated nodes.
+impl FromStr for Graph {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Graph, String> {
+        Graph::parse_with_terminals(s, &[], &[])
+    }
+}
+
+/// Percolation on a `Graph`. With the site model the nodes open and the edges are all present, and with the bond model
+/// every node is present and the edges open.
+pub struct GraphPercolation {
+    graph: Arc<Graph>,
+    model: Model,
+    adjacent: Vec<Vec<usize>>,
+    open: Vec<bool>,
+    open_count: usize,
+    // connects open nodes to each other, with the sources joined to a virtual source and the sinks to a virtual sink
+    qu: Box<UnionFind>,
+    // connects open nodes to each other only, for cluster sizes
+    clusters: Box<UnionFind>,
+}
+
+impl GraphPercolation {
+    pub fn new(graph: Arc<Graph>, model: Model) -> GraphPercolation {
+        let nodes = graph.nodes;
+        let elements = match model {
+            Model::Site => nodes,
+            Model::Bond => graph.edges.len(),
+            Model::Directed(_) => panic!("Graphs have no up or down for directed percolation to flow along"),
+        };
+        let mut perc = GraphPercolation {
+            adjacent: if model == Model::Site { graph.adjacency() } else { Vec::new() },
+            graph: graph,
+            model: model,
+            open: iter::repeat(false).take(elements).collect(),
+            open_count: 0,
+            qu: UnionFindKind::WeightedQuickUnion.create(nodes + 2),
+            clusters: UnionFindKind::WeightedQuickUnion.create(nodes),
+        };
+        if model == Model::Bond {
+            // every node is present, so the sources and sinks are joined up front
+            perc.join_terminals(&(0 .. nodes).collect::<Vec<usize>>());
+        }
+        perc
+    }
+
+    fn virtual_source(&self) -> usize {
+        self.graph.nodes
+    }
+
+    fn virtual_sink(&self) -> usize {
+        self.graph.nodes + 1
+    }
+
+    fn join_terminals(&mut self, nodes: &[usize]) {
+        let (source, sink) = (self.virtual_source(), self.virtual_sink());
+        for &node in nodes {
+            if self.graph.is_source(node) {
+                self.qu.union(node, source);
+            }
+            if self.graph.is_sink(node) {
+                self.qu.union(node, sink);
+            }
+        }
+    }
+
+    pub fn graph(&self) -> &Graph {
+        &self.graph
+    }
+
+    pub fn open_node(&mut self, node: usize) {
+        assert!(self.model == Model::Site, "Nodes only open in the site model");
+        if self.open[node] {
+            return;
+        }
+        self.open[node] = true;
+        self.open_count += 1;
+        self.join_terminals(&[node]);
+        for i in 0 .. self.adjacent[node].len() {
+            let neighbour = self.adjacent[node][i];
+            if self.open[neighbour] {
+                self.qu.union(node, neighbour);
+                self.clusters.union(node, neighbour);
+            }
+        }
+    }
+
+    pub fn open_edge(&mut self, edge: usize) {
+        assert!(self.model == Model::Bond, "Edges only open in the bond model");
+        if self.open[edge] {
+            return;
+        }
+        self.open[edge] = true;
+        self.open_count += 1;
+        let (p, q) = self.graph.edges[edge];
+        self.qu.union(p, q);
+        self.clusters.union(p, q);
+    }
+
+    /// Whether the node (in the site model) or the edge (in the bond model) is open.
+    pub fn is_open(&self, element: usize) -> bool {
+        self.open[element]
+    }
+
+    /// Returns the number of nodes in the cluster containing `node`, or 0 if it is a closed node.
+    pub fn cluster_size_of_node(&self, node: usize) -> usize {
+        if self.model == Model::Site && !self.open[node] {
+            0
+        } else {
+            self.clusters.component_size(node)
+        }
+    }
+
+    pub fn percolates(&self) -> bool {
+        self.qu.connected(self.virtual_source(), self.virtual_sink())
+    }
+
+    /// Whether the system would percolate if the node (in the site model) or the edge (in the bond model) were open
+    /// as well, without opening it.
+    pub fn would_percolate(&self, element: usize) -> bool {
+        if self.percolates() || self.open[element] {
+            return self.percolates();
+        }
+        match self.model {
+            Model::Site => {
+                let touches = |terminals: &[usize], terminal: usize| terminals.contains(&element) ||
+                    self.adjacent[element].iter().any(|&n| self.open[n] && self.qu.connected(n, terminal));
+                touches(&self.graph.sources, self.virtual_source()) && touches(&self.graph.sinks, self.virtual_sink())
+            },
+            Model::Bond => {
+                let (p, q) = self.graph.edges[element];
+                let touches = |terminal: usize| self.qu.connected(p, terminal) || self.qu.connected(q, terminal);
+                touches(self.virtual_source()) && touches(self.virtual_sink())
+            },
+            Model::Directed(_) => unreachable!(),
+        }
+    }
+}
+
+impl System for GraphPercolation {
+    fn element_count(&self) -> usize {
+        self.open.len()
+    }
+
+    fn open_element(&mut self, element: usize) {
+        match self.model {
+            Model::Site => self.open_node(element),
+            Model::Bond => self.open_edge(element),
+            Model::Directed(_) => unreachable!(),
+        }
+    }
+
+    fn number_of_open_elements(&self) -> usize {
+        self.open_count
+    }
+
+    fn percolates(&self) -> bool {
+        GraphPercolation::percolates(self)
+    }
+
+    fn would_percolate(&self, element: usize) -> bool {
+        GraphPercolation::would_percolate(self, element)
+    }
+
+    fn cluster_size(&self, element: usize) -> usize {
+        match self.model {
+            Model::Site => self.cluster_size_of_node(element),
+            Model::Bond => self.cluster_size_of_node(self.graph.edges[element].0),
+            Model::Directed(_) => unreachable!(),
+        }
+    }
+}
+
+/// Runs `times` trials on `graph` spread over at most `jobs` threads. As with `percolation::simulate_multiple`, the
+/// results only depend on `seed`.
+pub fn simulate_graph(graph: Arc<Graph>, model: Model, times: usize, jobs: u32, seed: usize) -> PercolationStats {
+    let mut results: Vec<Option<f32>> = iter::repeat(None).take(times).collect();
+    percolation::run_trials(times, jobs, move |trial| {
+        let mut perc = GraphPercolation::new(graph.clone(), model);
+        percolation::percolate(&mut perc, &mut percolation::trial_rng(seed, trial))
+    }, |trial, result| results[trial] = Some(result));
+    PercolationStats::new(results.into_iter().filter_map(|result| result).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use super::{Graph, GraphPercolation, simulate_graph};
+    use percolation::Model;
+
+    // a diamond from 0 to 3 with a tail hanging off 3
+    const DIAMOND: &'static str = "# diamond\nsource 0\nsink 3\n0 1\n0 2 1.5\n1 3\n2 3\n3 4\n";
+
+    #[test]
+    fn edge_lists_parse_with_sources_and_sinks() {
+        let graph = DIAMOND.parse::<Graph>().unwrap();
+        assert_eq!(graph.nodes, 5);
+        assert_eq!(graph.edges, vec![(0, 1), (0, 2), (1, 3), (2, 3), (3, 4)]);
+        assert_eq!((graph.sources.clone(), graph.sinks.clone()), (vec![0], vec![3]));
+
+        assert!("0 1\n".parse::<Graph>().is_err());
+        assert!("source 0\nsink 2\n0 1\n".parse::<Graph>().is_err());
+        assert!(Graph::new(2, vec![(0, 2)], vec![0], vec![1]).is_err());
+        assert!(graph.is_source(0) && !graph.is_source(3) && graph.is_sink(3));
+
+        // terminals given separately join the ones in the file, and errors still point at the file's lines
+        let extra = Graph::parse_with_terminals("0 1\n1 2\n", &[0], &[2]).unwrap();
+        assert_eq!((extra.sources.clone(), extra.sinks.clone()), (vec![0], vec![2]));
+        assert_eq!(Graph::parse_with_terminals("0 1\n1 x\n", &[0], &[1]).unwrap_err(),
+            "Line 2: expected a node number, found 'x'");
+
+        // gaps in the numbering are closed up rather than becoming isolated nodes
+        let sparse = "source 10\nsink 30\n10 20\n20 30\n".parse::<Graph>().unwrap();
+        assert_eq!(sparse.nodes, 3);
+        assert_eq!(sparse.edges, vec![(0, 1), (1, 2)]);
+        assert_eq!((sparse.sources.clone(), sparse.sinks.clone()), (vec![0], vec![2]));
+        let mut perc = GraphPercolation::new(Arc::new(sparse), Model::Site);
+        for node in 0 .. 3 {
+            perc.open_node(node);
+        }
+        assert!(perc.percolates());
+    }
+
+    #[test]
+    fn node_percolation_needs_an_open_chain_of_nodes() {
+        let mut perc = GraphPercolation::new(Arc::new(DIAMOND.parse::<Graph>().unwrap()), Model::Site);
+        perc.open_node(0);
+        perc.open_node(3);
+        assert!(!perc.percolates());
+
+        perc.open_node(2);
+        assert!(perc.percolates());
+        assert_eq!(perc.cluster_size_of_node(3), 3);
+        assert_eq!(perc.cluster_size_of_node(1), 0);
+    }
+
+    #[test]
+    fn edge_percolation_needs_an_open_chain_of_edges() {
+        let mut perc = GraphPercolation::new(Arc::new(DIAMOND.parse::<Graph>().unwrap()), Model::Bond);
+        perc.open_edge(0);
+        perc.open_edge(3);
+        perc.open_edge(4);
+        assert!(!perc.percolates());
+
+        perc.open_edge(2);
+        assert!(perc.percolates());
+        assert_eq!(perc.cluster_size_of_node(4), 5);
+    }
+
+    #[test]
+    fn graph_simulations_only_depend_on_the_seed() {
+        let graph = Arc::new(DIAMOND.parse::<Graph>().unwrap());
+        let sequential = simulate_graph(graph.clone(), Model::Bond, 10, 1, 5);
+
+        assert_eq!(sequential.results(), simulate_graph(graph, Model::Bond, 10, 3, 5).results());
+        // at least two of the five edges are needed to get from 0 to 3, and at most four
+        assert!(sequential.results().iter().all(|&r| r >= 0.4 && r <= 0.8));
+    }
+}
@@ -0,0 +1,202 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::iter;
+use lattice::{Boundary, Lattice};
+use percolation::{self, Percolation, Simulation};
+use rand::Rng;
+
+/// A site on the boundary of the invaded cluster, waiting to be invaded.
+#[derive(Debug)]
+struct Candidate {
+    strength: f32,
+    site: usize,
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // compare other to self to reverse the order to use BinaryHeap as a min-heap instead of a max-heap, breaking
+        // ties by site so that invasion doesn't depend on the heap's internals
+        match other.strength.partial_cmp(&self.strength).unwrap() { // safe: strengths are never NaN
+            Ordering::Equal => other.site.cmp(&self.site),
+            ordering => ordering,
+        }
+    }
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+/// The outcome of invading a lattice: every site's strength, and the order in which sites were invaded.
+#[derive(Clone, Debug)]
+pub struct Invasion {
+    pub lattice: Lattice,
+    pub strengths: Vec<f32>,
+    pub order: Vec<usize>,
+}
+
+impl Invasion {
+    /// Returns the invaded sites in increasing order.
+    pub fn invaded_sites(&self) -> Vec<usize> {
+        let mut sites = self.order.clone();
+        sites.sort();
+        sites
+    }
+
+    /// Returns the strength of each site in the order they were invaded.
+    pub fn accepted_strengths(&self) -> Vec<f32> {
+        self.order.iter().map(|&site| self.strengths[site]).collect()
+    }
+
+    pub fn invaded_fraction(&self) -> f32 {
+        self.order.len() as f32 / self.lattice.site_count() as f32
+    }
+
+    /// Splits strengths into `bins` equally wide bins over [0, 1), and returns the fraction of the sites in each bin
+    /// which were invaded (NaN for a bin without any sites). On large lattices this approaches a step down from 1 to
+    /// 0 at the percolation threshold.
+    pub fn acceptance_profile(&self, bins: usize) -> Vec<f32> {
+        let (accepted, total) = self.acceptance_counts(bins);
+        accepted.iter().zip(total.iter()).map(|(&accepted, &total)| accepted as f32 / total as f32).collect()
+    }
+
+    /// Returns the number of invaded sites and of all sites in each bin of strengths.
+    pub fn acceptance_counts(&self, bins: usize) -> (Vec<usize>, Vec<usize>) {
+        assert!(bins > 0, "An acceptance profile needs at least one bin");
+        let bin = |strength: f32| {
+            let bin = (strength * bins as f32) as usize;
+            if bin < bins { bin } else { bins - 1 }
+        };
+        let mut accepted: Vec<usize> = iter::repeat(0).take(bins).collect();
+        let mut total: Vec<usize> = iter::repeat(0).take(bins).collect();
+        for &strength in self.strengths.iter() {
+            total[bin(strength)] += 1;
+        }
+        for &site in self.order.iter() {
+            accepted[bin(self.strengths[site])] += 1;
+        }
+        (accepted, total)
+    }
+}
+
+/// Gives each site of `sim`'s lattice a random strength, and grows an invaded cluster from the top row by always
+/// invading the weakest site on its boundary, until it reaches the bottom row. A torus has no top or bottom row to
+/// invade between, so it can't be invaded.
+pub fn invade<R: Rng>(sim: &Simulation, rng: &mut R) -> Invasion {
+    let lattice = sim.lattice;
+    assert!(lattice.boundary != Boundary::Torus, "A torus has no top row to invade from");
+    let sites = lattice.site_count();
+    let strengths = (0 .. sites).map(|_| rng.gen::<f32>()).collect::<Vec<f32>>();
+
+    // the invaded sites are the open sites, so that the grid can tell when they span the lattice
+    let mut perc = Percolation::with_lattice(lattice, sim.union_find);
+    let mut queued: Vec<bool> = iter::repeat(false).take(sites).collect();
+    let mut boundary = BinaryHeap::new();
+    for site in (0 .. sites).filter(|&site| lattice.is_top(site)) {
+        queued[site] = true;
+        boundary.push(Candidate { strength: strengths[site], site: site });
+    }
+
+    let mut order = Vec::new();
+    while !perc.percolates() {
+        let site = boundary.pop().unwrap().site; // safe: the cluster must span before it runs out of sites to invade
+        perc.open_site(site);
+        order.push(site);
+        for neighbour in lattice.neighbours(site) {
+            if !queued[neighbour] {
+                queued[neighbour] = true;
+                boundary.push(Candidate { strength: strengths[neighbour], site: neighbour });
+            }
+        }
+    }
+    Invasion { lattice: lattice, strengths: strengths, order: order }
+}
+
+/// Runs `times` invasions of `sim` spread over at most `jobs` threads, in trial order. As with
+/// `percolation::simulate_multiple`, the invasions only depend on `seed`.
+pub fn simulate_invasions(sim: &Simulation, times: usize, jobs: u32, seed: usize) -> Vec<Invasion> {
+    let sim = sim.clone();
+    let mut invasions: Vec<Option<Invasion>> = iter::repeat(None).take(times).collect();
+    percolation::run_trials(times, jobs, move |trial| invade(&sim, &mut percolation::trial_rng(seed, trial)),
+            |trial, invasion| invasions[trial] = Some(invasion));
+    // only the invasions which finished before any interruption
+    invasions.into_iter().filter_map(|invasion| invasion).collect()
+}
+
+/// Pools the acceptance profiles of several invasions, weighting each by how many sites it has in each bin.
+pub fn pooled_acceptance_profile(invasions: &[Invasion], bins: usize) -> Vec<f32> {
+    let mut accepted: Vec<usize> = iter::repeat(0).take(bins).collect();
+    let mut total: Vec<usize> = iter::repeat(0).take(bins).collect();
+    for invasion in invasions {
+        let (invasion_accepted, invasion_total) = invasion.acceptance_counts(bins);
+        for bin in 0 .. bins {
+            accepted[bin] += invasion_accepted[bin];
+            total[bin] += invasion_total[bin];
+        }
+    }
+    accepted.iter().zip(total.iter()).map(|(&accepted, &total)| accepted as f32 / total as f32).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{invade, pooled_acceptance_profile, simulate_invasions};
+    use lattice::Lattice;
+    use percolation::{Simulation, trial_rng};
+
+    #[test]
+    fn invasion_grows_a_connected_cluster_from_top_to_bottom() {
+        let sim = Simulation::new(8);
+        let invasion = invade(&sim, &mut trial_rng(4, 0));
+        let lattice = Lattice::square(8);
+
+        assert!(lattice.is_top(invasion.order[0]));
+        assert!(lattice.is_bottom(invasion.order[invasion.order.len() - 1]));
+        // every site but the first row's joins a site invaded before it
+        for (step, &site) in invasion.order.iter().enumerate() {
+            assert!(lattice.is_top(site) || lattice.neighbours(site).iter().any(|n| invasion.order[..step].contains(n)));
+        }
+        let mut sites = invasion.invaded_sites();
+        sites.dedup();
+        assert_eq!(sites.len(), invasion.order.len());
+    }
+
+    #[test]
+    fn invasion_always_takes_the_weakest_boundary_site() {
+        let invasion = invade(&Simulation::new(6), &mut trial_rng(2, 0));
+        let lattice = Lattice::square(6);
+
+        for step in 0 .. invasion.order.len() {
+            let invaded = &invasion.order[..step];
+            let weakest = (0 .. lattice.site_count())
+                .filter(|site| !invaded.contains(site))
+                .filter(|&site| lattice.is_top(site) || lattice.neighbours(site).iter().any(|n| invaded.contains(n)))
+                .map(|site| invasion.strengths[site])
+                .fold(1.0f32, |weakest, strength| weakest.min(strength));
+            assert_eq!(invasion.strengths[invasion.order[step]], weakest);
+        }
+    }
+
+    #[test]
+    fn acceptance_profile_counts_invaded_sites_by_strength() {
+        let invasions = simulate_invasions(&Simulation::new(10), 3, 2, 1);
+        let (accepted, total) = invasions[0].acceptance_counts(4);
+        let profile = pooled_acceptance_profile(&invasions[..], 4);
+
+        assert_eq!(accepted.iter().fold(0, |sum, &a| sum + a), invasions[0].order.len());
+        assert_eq!(total.iter().fold(0, |sum, &t| sum + t), 100);
+        // the weakest sites are the most likely to be invaded
+        assert_eq!(invasions[0].acceptance_profile(4)[0], accepted[0] as f32 / total[0] as f32);
+        assert!(profile[0] > profile[3]);
+        assert_eq!(simulate_invasions(&Simulation::new(10), 3, 1, 1)[2].order, invasions[2].order);
+    }
+}
@@ -0,0 +1,288 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Displacement between neighbouring sites in (rows, cols, layers), ignoring any wrapping around the edges.
+pub type Step = [isize; 3];
+
+/// What lies beyond the edges of a lattice.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Boundary {
+    /// Nothing: the top and bottom rows are the ends of the lattice.
+    Fixed,
+    /// The lattice wraps around horizontally (across columns, and across layers in 3D), like a cylinder standing on
+    /// its end.
+    Cylinder,
+    /// The lattice wraps around in every direction, so it has no top or bottom row at all.
+    Torus,
+}
+
+impl fmt::Display for Boundary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", match *self {
+            Boundary::Fixed => "fixed",
+            Boundary::Cylinder => "cylinder",
+            Boundary::Torus => "torus",
+        })
+    }
+}
+
+impl FromStr for Boundary {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Boundary, String> {
+        match s {
+            "fixed" => Ok(Boundary::Fixed),
+            "cylinder" => Ok(Boundary::Cylinder),
+            "torus" => Ok(Boundary::Torus),
+            _ => Err(format!("Unknown boundary '{}' (expected one of: fixed, cylinder, torus)", s)),
+        }
+    }
+}
+
+/// The shape of a grid of sites: `rows` x `cols` sites in each of `layers` layers, so that a 2D grid is a lattice
+/// with a single layer. Sites are numbered from 0, row by row and then layer by layer. The top of the lattice is
+/// row 0 (across every layer) and the bottom is the last row.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Lattice {
+    pub rows: usize,
+    pub cols: usize,
+    pub layers: usize,
+    pub boundary: Boundary,
+}
+
+impl Lattice {
+    pub fn square(n: usize) -> Lattice {
+        Lattice::rectangle(n, n)
+    }
+
+    pub fn rectangle(rows: usize, cols: usize) -> Lattice {
+        Lattice::cuboid(rows, cols, 1)
+    }
+
+    pub fn cube(n: usize) -> Lattice {
+        Lattice::cuboid(n, n, n)
+    }
+
+    pub fn cuboid(rows: usize, cols: usize, layers: usize) -> Lattice {
+        Lattice { rows: rows, cols: cols, layers: layers, boundary: Boundary::Fixed }
+    }
+
+    pub fn with_boundary(&self, boundary: Boundary) -> Lattice {
+        Lattice { boundary: boundary, ..*self }
+    }
+
+    pub fn site_count(&self) -> usize {
+        self.rows * self.cols * self.layers
+    }
+
+    pub fn is_2d(&self) -> bool {
+        self.layers == 1
+    }
+
+    /// Returns the site at the given 0-based coordinates.
+    pub fn index(&self, row: usize, col: usize, layer: usize) -> usize {
+        (layer * self.rows + row) * self.cols + col
+    }
+
+    /// Returns the site at the given 1-based coordinates, as used by `Percolation`, if it is on the lattice.
+    pub fn site(&self, i: usize, j: usize, k: usize) -> Option<usize> {
+        if i >= 1 && i <= self.rows && j >= 1 && j <= self.cols && k >= 1 && k <= self.layers {
+            Some(self.index(i - 1, j - 1, k - 1))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the 0-based (row, col, layer) of a site.
+    pub fn coordinates(&self, site: usize) -> (usize, usize, usize) {
+        (site / self.cols % self.rows, site % self.cols, site / (self.cols * self.rows))
+    }
+
+    /// Whether the site is in the top row. A torus has no top row.
+    pub fn is_top(&self, site: usize) -> bool {
+        self.boundary != Boundary::Torus && self.coordinates(site).0 == 0
+    }
+
+    /// Whether the site is in the bottom row. A torus has no bottom row.
+    pub fn is_bottom(&self, site: usize) -> bool {
+        self.boundary != Boundary::Torus && self.coordinates(site).0 == self.rows - 1
+    }
+
+    /// Returns which of the (rows, cols, layers) directions wrap around.
+    pub fn periodic(&self) -> [bool; 3] {
+        match self.boundary {
+            Boundary::Fixed => [false, false, false],
+            Boundary::Cylinder => [false, true, true],
+            Boundary::Torus => [true, true, true],
+        }
+    }
+
+    /// Returns the sites which share a face with `site`. A periodic dimension of size 2 joins a site to the other one
+    /// in it both ways around, so that neighbour is listed twice.
+    pub fn neighbours(&self, site: usize) -> Vec<usize> {
+        self.steps(site).into_iter().map(|(neighbour, _)| neighbour).collect()
+    }
+
+    /// Returns the sites which share a face with `site`, each with the step taken to get there from `site`.
+    pub fn steps(&self, site: usize) -> Vec<(usize, Step)> {
+        let (row, col, layer) = self.coordinates(site);
+        let coordinates = [row, col, layer];
+        let sizes = [self.rows, self.cols, self.layers];
+        let periodic = self.periodic();
+
+        let mut steps: Vec<(usize, Step)> = Vec::with_capacity(6);
+        for axis in 0 .. 3 {
+            for &delta in [-1isize, 1].iter() {
+                let moved = coordinates[axis] as isize + delta;
+                let wrapped = if moved >= 0 && moved < sizes[axis] as isize {
+                    moved as usize
+                } else if periodic[axis] {
+                    ((moved + sizes[axis] as isize) % sizes[axis] as isize) as usize
+                } else {
+                    continue;
+                };
+                let mut neighbour = coordinates;
+                neighbour[axis] = wrapped;
+                let neighbour = self.index(neighbour[0], neighbour[1], neighbour[2]);
+                // a periodic dimension of size 1 wraps onto the site itself, which isn't a neighbour; one of size 2
+                // wraps onto the neighbour on the other side, which is a second, distinct bond to it
+                if neighbour != site {
+                    let mut step = [0, 0, 0];
+                    step[axis] = delta;
+                    steps.push((neighbour, step));
+                }
+            }
+        }
+        steps
+    }
+
+    /// Returns the step from `site` to its neighbour `neighbour`, or the first of the two steps when a periodic
+    /// dimension of size 2 joins them both ways around.
+    pub fn step_between(&self, site: usize, neighbour: usize) -> Option<Step> {
+        self.steps(site).into_iter().find(|&(other, _)| other == neighbour).map(|(_, step)| step)
+    }
+
+    /// Returns every bond between neighbouring sites once, as (lower site, higher site). Sites joined both ways
+    /// around a periodic dimension of size 2 have two bonds.
+    pub fn bonds(&self) -> Vec<(usize, usize)> {
+        self.bond_steps().into_iter().map(|(p, q, _)| (p, q)).collect()
+    }
+
+    /// Returns the bonds in the same order as `bonds`, each with the step from its lower site to its higher site.
+    pub fn bond_steps(&self) -> Vec<(usize, usize, Step)> {
+        let mut bonds = Vec::with_capacity(self.site_count() * 3);
+        for site in 0 .. self.site_count() {
+            for (neighbour, step) in self.steps(site) {
+                if site < neighbour {
+                    bonds.push((site, neighbour, step));
+                }
+            }
+        }
+        bonds
+    }
+}
+
+impl fmt::Display for Lattice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_2d() {
+            write!(f, "{}x{}", self.rows, self.cols)
+        } else {
+            write!(f, "{}x{}x{}", self.rows, self.cols, self.layers)
+        }
+    }
+}
+
+/// Parses `N` as an NxN square, `RxC` as a rectangle and `RxCxL` as a box of L layers (such as a cube).
+impl FromStr for Lattice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Lattice, String> {
+        let dimensions: Result<Vec<usize>, _> = s.split('x').map(|d| d.trim().parse::<usize>()).collect();
+        match dimensions {
+            Ok(ref d) if d.iter().any(|&d| d == 0) => Err(format!("Lattice '{}' has no sites", s)),
+            Ok(ref d) if d.len() == 1 => Ok(Lattice::square(d[0])),
+            Ok(ref d) if d.len() == 2 => Ok(Lattice::rectangle(d[0], d[1])),
+            Ok(ref d) if d.len() == 3 => Ok(Lattice::cuboid(d[0], d[1], d[2])),
+            _ => Err(format!("Failed to parse lattice '{}' (expected N, RxC or RxCxL)", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Boundary, Lattice};
+
+    #[test]
+    fn coordinates_round_trip_through_index() {
+        let lattice = Lattice::cuboid(3, 4, 5);
+        for site in 0 .. lattice.site_count() {
+            let (row, col, layer) = lattice.coordinates(site);
+            assert_eq!(lattice.index(row, col, layer), site);
+        }
+    }
+
+    #[test]
+    fn neighbours_stay_on_the_lattice() {
+        let square = Lattice::square(3);
+        assert_eq!(square.neighbours(0), vec![3, 1]);
+        assert_eq!(square.neighbours(4), vec![1, 7, 3, 5]);
+
+        let rectangle = Lattice::rectangle(2, 3);
+        assert_eq!(rectangle.neighbours(5), vec![2, 4]);
+
+        let cube = Lattice::cube(3);
+        assert_eq!(cube.neighbours(13).len(), 6);
+        assert_eq!(cube.neighbours(0), vec![3, 1, 9]);
+    }
+
+    #[test]
+    fn periodic_neighbours_wrap_around() {
+        let cylinder = Lattice::square(3).with_boundary(Boundary::Cylinder);
+        assert_eq!(cylinder.steps(0), vec![(3, [1, 0, 0]), (2, [0, -1, 0]), (1, [0, 1, 0])]);
+        assert!(cylinder.is_top(0));
+
+        let torus = Lattice::square(3).with_boundary(Boundary::Torus);
+        assert_eq!(torus.neighbours(0), vec![6, 3, 2, 1]);
+        assert!(!torus.is_top(0) && !torus.is_bottom(6));
+        assert_eq!(torus.bonds().len(), 2 * 9);
+    }
+
+    #[test]
+    fn tiny_periodic_lattices_do_not_neighbour_themselves() {
+        let torus = Lattice::rectangle(1, 2).with_boundary(Boundary::Torus);
+        assert_eq!(torus.neighbours(0), vec![1, 1]);
+        assert_eq!(torus.bond_steps(), vec![(0, 1, [0, -1, 0]), (0, 1, [0, 1, 0])]);
+    }
+
+    #[test]
+    fn periodic_dimensions_of_size_two_have_two_bonds_per_pair() {
+        // every site of a torus has two bonds per dimension, however narrow it is
+        assert_eq!(Lattice::square(2).with_boundary(Boundary::Torus).bonds().len(), 2 * 4);
+        assert_eq!(Lattice::rectangle(3, 2).with_boundary(Boundary::Cylinder).bonds().len(), 2 * 6 + 3);
+    }
+
+    #[test]
+    fn bonds_connect_each_pair_of_neighbours_once() {
+        assert_eq!(Lattice::rectangle(2, 2).bonds(), vec![(0, 2), (0, 1), (1, 3), (2, 3)]);
+        assert_eq!(Lattice::cube(3).bonds().len(), 3 * 3 * 2 * 3);
+    }
+
+    #[test]
+    fn top_and_bottom_are_first_and_last_rows_of_every_layer() {
+        let cube = Lattice::cube(2);
+        let top = (0 .. 8).filter(|&site| cube.is_top(site)).collect::<Vec<usize>>();
+        let bottom = (0 .. 8).filter(|&site| cube.is_bottom(site)).collect::<Vec<usize>>();
+        assert_eq!(top, vec![0, 1, 4, 5]);
+        assert_eq!(bottom, vec![2, 3, 6, 7]);
+    }
+
+    #[test]
+    fn lattices_parse_from_their_dimensions() {
+        assert_eq!("7".parse::<Lattice>(), Ok(Lattice::square(7)));
+        assert_eq!("7x9".parse::<Lattice>(), Ok(Lattice::rectangle(7, 9)));
+        assert_eq!("4x4x4".parse::<Lattice>(), Ok(Lattice::cube(4)));
+        assert_eq!(Lattice::cube(4).to_string(), "4x4x4");
+        assert!("4x0".parse::<Lattice>().is_err());
+        assert!("4xx4".parse::<Lattice>().is_err());
+    }
+}
@@ -3,18 +3,55 @@
 #![feature(convert)]
 
 extern crate getopts;
+#[cfg(unix)]
+extern crate libc;
 #[cfg(test)]
 extern crate quickcheck;
 extern crate rand;
+extern crate time;
 #[cfg(test)]
 extern crate test;
 
+mod bitset;
+mod bond;
+mod checkpoint;
+mod clusters;
 mod conversions;
+mod curve;
+mod directed;
+mod graph;
+mod invasion;
+mod lattice;
 mod unionfind;
+mod wrapping;
+mod output;
 mod percolation;
+mod progress;
+mod render;
+mod scaling;
+mod replay;
+mod stats;
+mod strategy;
 #[cfg(test)]
 mod benchmarks;
 
+use std::fmt::Display;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::process;
+use std::str::FromStr;
+use std::sync::Arc;
+use getopts::Matches;
+use graph::Graph;
+use lattice::{Boundary, Lattice};
+use output::{Format, GraphRunInfo, RunInfo};
+use percolation::{MemoryUsage, Model, Simulation};
+use render::ImageFormat;
+use replay::{Input, parse_expectations};
+use strategy::Strategy;
+use unionfind::UnionFindKind;
+
 fn main() {
     use std::convert::AsRef;
     use std::env;
@@ -23,40 +60,580 @@ fn main() {
     let args: Vec<String> = env::args().collect();
 
     let mut opts = Options::new();
-    opts.reqopt("n", "size", "Size of each side of the percolation board", "SIZE");
+    opts.optopt("n", "size", "Size of each side of a square percolation board", "SIZE");
+    opts.optopt("l", "lattice", "Shape of the percolation board instead of a square: ROWSxCOLS for a rectangle or \
+            NxNxN for a cube", "LATTICE");
     opts.optopt("t", "times", "Number of percolations to simulate", "TIMES");
     opts.optopt("j", "jobs", "Maximum number of jobs (threads) to use", "JOBS");
+    opts.optopt("b", "boundary", "Boundary of the board: fixed (default), cylinder to wrap around horizontally or \
+            torus to wrap around in every direction", "BOUNDARY");
+    opts.optopt("m", "model", "Percolation model: site (default) to open sites, bond to open the bonds between them, \
+            directed for sites only reached from the site above them or directed-tilted for sites reached from the \
+            sites diagonally above them", "MODEL");
+    opts.optopt("", "strategy", "Order to open elements in: uniform (default), row-major, correlated[:RADIUS] for \
+            clumps of sites, adversarial to put off each element which would percolate while any other is left, or \
+            map:FILE for a file of per-site probabilities", "STRATEGY");
+    opts.optmulti("", "uf", "Union-find to use: quick-find, quick-union, weighted (default) or weighted-compressed. \
+            Repeat to compare several", "UF");
+    opts.optopt("", "seed", "Seed for the random number generators, to reproduce an earlier run (default: random)", "SEED");
+    opts.optopt("f", "format", "Output format: text (default), csv or json", "FORMAT");
+    opts.optopt("", "precision", "Instead of a fixed number of trials, run trials until the 95% confidence interval of \
+            the mean is within this much of it", "EPSILON");
+    opts.optopt("", "max-times", "Most trials to run with --precision (default 100000)", "TIMES");
+    opts.optopt("", "bins", "Number of bins in the histogram of thresholds (default 10)", "BINS");
+    opts.optopt("", "frames", "Directory to write images of the first trial to as it runs (site model only)", "DIR");
+    opts.optopt("", "frame-every", "Number of sites to open between frames (default 1)", "K");
+    opts.optopt("", "frame-format", "Image format of frames: png (default) or ppm", "FORMAT");
+    opts.optopt("", "scale", "Size in pixels of each site in frames (default 4)", "PIXELS");
+    opts.optopt("", "fractions", "Comma separated fractions of open sites to sample clusters at \
+            (default 0.1,0.2,...,0.9)", "FRACTIONS");
+    opts.optopt("", "sizes", "Comma separated sizes of the square boards to sweep over (default 16,32,64,128,256)",
+            "SIZES");
+    opts.optopt("", "nu", "Correlation length exponent to fit a sweep with (default 4/3, exact in 2D; required for \
+            directed models)", "NU");
+    opts.optopt("", "sources", "Comma separated nodes to add to the sources of a graph", "NODES");
+    opts.optopt("", "sinks", "Comma separated nodes to add to the sinks of a graph", "NODES");
+    opts.optopt("", "expected", "File of expected results to check a replay against", "FILE");
+    opts.optopt("", "checkpoint", "File to save the results so far to every so often, so that the run can be resumed \
+            with --resume", "FILE");
+    opts.optopt("", "checkpoint-every", "Seconds between saving checkpoints (default 60)", "SECONDS");
+    opts.optopt("", "resume", "Checkpoint file to continue a run from, with the settings saved in it rather than \
+            ones given on the command line. Further checkpoints are saved to the same file unless --checkpoint is \
+            given", "FILE");
+    opts.optflag("", "progress", "Report the number of trials done and the estimated time left on stderr");
     opts.optflag("h", "help", "print this help menu");
     match opts.parse(args.tail()) {
         Ok(matches) => {
             if matches.opt_present("h") {
-                let brief = format!("{}\n\nGathers percolation statistics according to the options provided.",
+                let brief = format!("{} [COMMAND]\n\n\
+                        Commands:\n    \
+                        simulate    Gathers percolation thresholds according to the options provided (default)\n    \
+                        curve       Opens every element of each trial and reports, for every number of open elements,\n                \
+                        how often the system percolates and how large its largest cluster is\n    \
+                        clusters    Samples the sizes of the open clusters at several fractions of open sites\n    \
+                        invasion    Grows invasion percolation clusters from the top row to the bottom row\n    \
+                        memory      Reports the bytes of memory each part of a site percolation system takes up, for \
+                        each --uf\n    \
+                        replay FILE Opens the sites listed in a course-format input file in order\n    \
+                        sweep       Gathers percolation thresholds over several sizes of board, and extrapolates \
+                        them to an infinite board\n    \
+                        graph FILE  Gathers percolation thresholds on the graph in an edge list file, opening its \
+                        nodes (-m site) or edges (-m bond)",
                         opts.short_usage(&args[0]));
                 print!("{}", opts.usage(brief.as_ref()));
             } else {
-                let size_str = matches.opt_str("n").expect("-n (or --size) should have been a required option");
-                let times_str = matches.opt_str("t").unwrap_or("1".to_string());
-                let jobs_str = matches.opt_str("j").unwrap_or("1".to_string());
-
-                let parse_result = size_str.parse::<usize>().and_then(|size| {
-                    times_str.parse::<usize>().and_then(|times| {
-                        jobs_str.parse::<u32>().map(|jobs| (size, times, jobs))
-                    })
-                });
-                match parse_result {
-                    Ok((size, times, jobs)) => {
-                        use percolation;
-
-                        println!("Running {num} percolation(s) on a {n}x{n} board using max {jobs} job(s)",
-                            num=times, n=size, jobs=jobs);
-                        let stats = percolation::simulate_multiple(size, times, jobs);
-                        // println!("{:?}", stats);
-                        println!("Mean: {}", stats.mean());
-                    },
-                    Err(_) => println!("Failed to convert arguments of -t,  -n, or -j to numbers (try --help)")
+                if matches.opt_present("progress") {
+                    progress::report_progress();
+                }
+                let result = match matches.free.first().map(|command| command.as_ref()) {
+                    None | Some("simulate") => run_simulations(&matches),
+                    Some("curve") => run_curve(&matches),
+                    Some("clusters") => run_clusters(&matches),
+                    Some("invasion") => run_invasion(&matches),
+                    Some("memory") => run_memory(&matches),
+                    Some("replay") => run_replay(&matches),
+                    Some("sweep") => run_sweep(&matches),
+                    Some("graph") => run_graph(&matches),
+                    Some(command) => Err(format!("Unknown command '{}'", command)),
                 };
+                if progress::interrupted() {
+                    let _ = writeln!(io::stderr(), "Interrupted: the results only include the trials which finished");
+                }
+                if let Err(e) = result {
+                    fail(&format!("{} (try --help)", e));
+                }
             }
         },
-        Err(f) => println!("{}\nUse --help for usage information", f.to_string()),
+        Err(f) => fail(&format!("{}\nUse --help for usage information", f.to_string())),
+    }
+}
+
+/// Reports an error on stderr, so that it doesn't end up mixed in with results, and exits with a failure status.
+fn fail(message: &str) -> ! {
+    let _ = io::stdout().flush();
+    let _ = writeln!(io::stderr(), "{}", message);
+    process::exit(1)
+}
+
+/// Parses the value of option `name`, or returns `default` if it wasn't given.
+fn parse_opt<T: FromStr>(matches: &Matches, name: &str, default: T) -> Result<T, String> where T::Err: Display {
+    match matches.opt_str(name) {
+        Some(s) => s.parse::<T>().map_err(|e| format!("Invalid argument for --{}: {}", name, e)),
+        None => Ok(default),
+    }
+}
+
+/// Parses --jobs, which needs at least one thread to run any trials on.
+fn parse_jobs(matches: &Matches) -> Result<u32, String> {
+    match try!(parse_opt(matches, "jobs", 1)) {
+        0 => Err("Invalid argument for --jobs: at least one job is needed to run trials".to_string()),
+        jobs => Ok(jobs),
+    }
+}
+
+fn parse_lattice(matches: &Matches) -> Result<Lattice, String> {
+    let boundary = try!(parse_opt(matches, "boundary", Boundary::Fixed));
+    let lattice = match (matches.opt_str("n"), matches.opt_str("l")) {
+        (Some(size_str), None) => try!(size_str.parse::<usize>().map(Lattice::square)
+            .map_err(|_| "Failed to convert argument of -n to a number".to_string())),
+        (None, Some(lattice_str)) => try!(lattice_str.parse::<Lattice>()),
+        (Some(_), Some(_)) => return Err("Only one of -n and --lattice may be given".to_string()),
+        (None, None) => return Err("One of -n or --lattice is required".to_string()),
+    };
+    let lattice = lattice.with_boundary(boundary);
+    try!(percolation::check_lattice(&lattice).map_err(|e| e.to_string()));
+    Ok(lattice)
+}
+
+/// Parses --strategy, and checks that it can open the elements of `model` on `lattice`.
+fn parse_strategy(matches: &Matches, lattice: &Lattice, model: Model) -> Result<Strategy, String> {
+    let strategy = match matches.opt_str("strategy") {
+        Some(s) => try!(Strategy::load(&s).map_err(|e| format!("Invalid argument for --strategy: {}", e))),
+        None => Strategy::Uniform,
+    };
+    try!(strategy.check(lattice, model));
+    Ok(strategy)
+}
+
+/// Fails if --strategy was given for a command which decides the order elements open in by itself.
+fn reject_strategy(matches: &Matches, command: &str) -> Result<(), String> {
+    if matches.opt_present("strategy") {
+        Err(format!("The {} command doesn't take --strategy", command))
+    } else {
+        Ok(())
+    }
+}
+
+/// Parses --model, and checks that it can run on a lattice with `boundary`.
+fn parse_model(matches: &Matches, boundary: Boundary) -> Result<Model, String> {
+    let model = try!(parse_opt(matches, "model", Model::Site));
+    match model {
+        Model::Directed(_) if boundary == Boundary::Torus =>
+            Err("Directed percolation needs a top row to flow down from, which a torus doesn't have".to_string()),
+        _ => Ok(model),
+    }
+}
+
+fn parse_union_finds(matches: &Matches) -> Result<Vec<UnionFindKind>, String> {
+    let uf_strs = matches.opt_strs("uf");
+    if uf_strs.is_empty() {
+        Ok(vec![UnionFindKind::WeightedQuickUnion])
+    } else {
+        uf_strs.iter().map(|uf_str| uf_str.parse::<UnionFindKind>()).collect()
+    }
+}
+
+fn run_simulations(matches: &Matches) -> Result<(), String> {
+    if matches.opt_present("checkpoint") || matches.opt_present("resume") {
+        return run_checkpointed(matches);
+    }
+    let times = try!(parse_opt(matches, "times", 1));
+    let jobs = try!(parse_jobs(matches));
+    let bins = try!(parse_opt(matches, "bins", 10));
+    let seed = try!(parse_opt(matches, "seed", rand::random::<usize>()));
+    let lattice = try!(parse_lattice(matches));
+    let ufs = try!(parse_union_finds(matches));
+    let format = try!(parse_opt(matches, "format", Format::Text));
+    let model = try!(parse_model(matches, lattice.boundary));
+    let every = try!(parse_opt(matches, "frame-every", 1));
+    let frame_format = try!(parse_opt(matches, "frame-format", ImageFormat::Png));
+    let scale = try!(parse_opt(matches, "scale", 4));
+    let max_times = try!(parse_opt(matches, "max-times", 100000));
+    let precision = match matches.opt_str("precision") {
+        Some(_) => Some(try!(parse_opt(matches, "precision", 0f32))),
+        None => None,
+    };
+    let strategy = try!(parse_strategy(matches, &lattice, model));
+
+    if let Some(dir) = matches.opt_str("frames") {
+        if model != Model::Site {
+            return Err("Frames can only be rendered for the site model".to_string());
+        }
+        if every == 0 || scale == 0 {
+            return Err("--frame-every and --scale must be positive".to_string());
+        }
+        let sim = Simulation { union_find: ufs[0], strategy: strategy.clone(), ..Simulation::on(lattice) };
+        try!(write_frames(&sim, seed, Path::new(&dir), every, scale, frame_format)
+            .map_err(|e| format!("Failed to write frames to {}: {}", dir, e)));
+    }
+
+    for (i, uf) in ufs.into_iter().enumerate() {
+        if format == Format::Text {
+            let num = match precision {
+                Some(precision) => format!("up to {} {} percolation(s), until within ±{},", max_times, model, precision),
+                None => format!("{} {} percolation(s)", times, model),
+            };
+            println!("Running {num} on a {lattice} board with {boundary} boundary, {uf} union-find and {strategy} strategy using max {jobs} job(s) and seed {seed}",
+                num=num, lattice=lattice, boundary=lattice.boundary, uf=uf, strategy=strategy, jobs=jobs, seed=seed);
+        }
+        let sim = Simulation { model: model, union_find: uf, strategy: strategy.clone(), ..Simulation::on(lattice) };
+        let start = time::precise_time_s();
+        let stats = match precision {
+            Some(precision) => percolation::simulate_until(&sim, precision, max_times, jobs, seed),
+            None => percolation::simulate_multiple(&sim, times, jobs, seed),
+        };
+        let info = RunInfo {
+            lattice: lattice,
+            model: model,
+            trials: stats.trials(),
+            jobs: jobs,
+            seed: seed,
+            union_find: uf,
+            strategy: Some(strategy.clone()),
+            wall_time_s: time::precise_time_s() - start,
+        };
+        // println!("{:?}", stats);
+        output::write_run(&mut io::stdout(), format, &info, &stats, bins, i == 0)
+            .ok().expect("Failed to write results to stdout");
+        if progress::interrupted() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Runs trials like `run_simulations`, saving a checkpoint every so often, either from the start or resuming from an
+/// earlier checkpoint.
+fn run_checkpointed(matches: &Matches) -> Result<(), String> {
+    let jobs = try!(parse_jobs(matches));
+    let bins = try!(parse_opt(matches, "bins", 10));
+    let format = try!(parse_opt(matches, "format", Format::Text));
+    let interval = try!(parse_opt(matches, "checkpoint-every", 60f64));
+    if matches.opt_present("precision") || matches.opt_present("frames") {
+        return Err("Checkpoints can't be combined with --precision or --frames".to_string());
+    }
+
+    let (mut checkpoint, path) = match matches.opt_str("resume") {
+        Some(resume) => {
+            let settings = ["size", "lattice", "boundary", "times", "seed", "uf", "model", "strategy"];
+            if let Some(setting) = settings.iter().find(|&&setting| matches.opt_present(setting)) {
+                return Err(format!("--{} can't be given with --resume, which runs with the settings saved in the \
+                        checkpoint", setting));
+            }
+            let checkpoint = try!(try!(read_file(&resume)).parse::<checkpoint::Checkpoint>()
+                .map_err(|e| format!("Failed to parse checkpoint {}: {}", resume, e)));
+            (checkpoint, matches.opt_str("checkpoint").unwrap_or(resume))
+        },
+        None => {
+            let times = try!(parse_opt(matches, "times", 1));
+            let seed = try!(parse_opt(matches, "seed", rand::random::<usize>()));
+            let lattice = try!(parse_lattice(matches));
+            let ufs = try!(parse_union_finds(matches));
+            let model = try!(parse_model(matches, lattice.boundary));
+            let strategy = try!(parse_strategy(matches, &lattice, model));
+            if ufs.len() > 1 {
+                return Err("Only one --uf may be given with checkpoints".to_string());
+            }
+            let sim = Simulation { model: model, union_find: ufs[0], strategy: strategy, ..Simulation::on(lattice) };
+            (checkpoint::Checkpoint::new(&sim, times, seed), matches.opt_str("checkpoint").unwrap())
+        },
+    };
+
+    let sim = checkpoint.sim.clone();
+    if format == Format::Text {
+        println!("Running {num} {model} percolation(s) on a {lattice} board with {boundary} boundary, {uf} union-find and {strategy} strategy using max {jobs} job(s) and seed {seed}, {finished} already finished, saving checkpoints to {path}",
+            num=checkpoint.times(), model=sim.model, lattice=sim.lattice, boundary=sim.lattice.boundary,
+            uf=sim.union_find, strategy=sim.strategy, jobs=jobs, seed=checkpoint.seed, finished=checkpoint.finished(), path=path);
+    }
+    let start = time::precise_time_s();
+    try!(checkpoint::run_from(&mut checkpoint, jobs, interval, |saved| saved.save(Path::new(&path)))
+        .map_err(|e| format!("Failed to save checkpoint {}: {}", path, e)));
+    let stats = checkpoint.stats();
+    let info = RunInfo {
+        lattice: sim.lattice,
+        model: sim.model,
+        trials: stats.trials(),
+        jobs: jobs,
+        seed: checkpoint.seed,
+        union_find: sim.union_find,
+        strategy: Some(sim.strategy.clone()),
+        wall_time_s: time::precise_time_s() - start,
+    };
+    output::write_run(&mut io::stdout(), format, &info, &stats, bins, true).ok().expect("Failed to write results to stdout");
+    Ok(())
+}
+
+/// Writes frames of the first trial of `sim` to numbered files in `dir`. It is the same trial as the first one
+/// `percolation::simulate_multiple` runs with `seed`, so the frames end at its first threshold.
+fn write_frames(sim: &Simulation, seed: usize, dir: &Path, every: usize, scale: usize, format: ImageFormat)
+        -> io::Result<()> {
+    try!(fs::create_dir_all(dir));
+    render::simulate_frames(sim, &mut percolation::trial_rng(seed, 0), every, scale, |number, image| {
+        let mut file = try!(File::create(&dir.join(format!("frame-{:05}.{}", number, format.extension()))));
+        image.write(&mut file, format)
+    }).map(|_| ())
+}
+
+fn run_curve(matches: &Matches) -> Result<(), String> {
+    let times = try!(parse_opt(matches, "times", 1));
+    let jobs = try!(parse_jobs(matches));
+    let seed = try!(parse_opt(matches, "seed", rand::random::<usize>()));
+    let lattice = try!(parse_lattice(matches));
+    let ufs = try!(parse_union_finds(matches));
+    let format = try!(parse_opt(matches, "format", Format::Text));
+    let model = try!(parse_model(matches, lattice.boundary));
+    let strategy = try!(parse_strategy(matches, &lattice, model));
+    if ufs.len() > 1 {
+        return Err("Only one --uf may be given for a curve".to_string());
+    }
+    if !ufs[0].tracks_sizes() {
+        // the largest cluster is looked up after every step, which would make each trial quadratic in its size
+        return Err(format!("A curve needs a union-find which tracks component sizes, such as weighted, not {}",
+            ufs[0]));
+    }
+
+    if format == Format::Text {
+        println!("Tracing {num} {model} percolation(s) on a {lattice} board with {boundary} boundary, {uf} union-find and {strategy} strategy using max {jobs} job(s) and seed {seed}",
+            num=times, model=model, lattice=lattice, boundary=lattice.boundary, uf=ufs[0], strategy=strategy, jobs=jobs,
+            seed=seed);
+    }
+    let sim = Simulation { model: model, union_find: ufs[0], strategy: strategy.clone(), ..Simulation::on(lattice) };
+    let start = time::precise_time_s();
+    let curve = curve::simulate_curve(&sim, times, jobs, seed);
+    let info = RunInfo {
+        lattice: lattice,
+        model: model,
+        trials: curve.trials(),
+        jobs: jobs,
+        seed: seed,
+        union_find: ufs[0],
+        strategy: Some(strategy),
+        wall_time_s: time::precise_time_s() - start,
+    };
+    output::write_curve(&mut io::stdout(), format, &info, &curve).ok().expect("Failed to write results to stdout");
+    Ok(())
+}
+
+fn read_file(filename: &str) -> Result<String, String> {
+    let mut content = String::new();
+    try!(File::open(filename).and_then(|mut file| file.read_to_string(&mut content))
+        .map_err(|e| format!("Failed to read {}: {}", filename, e)));
+    Ok(content)
+}
+
+fn run_replay(matches: &Matches) -> Result<(), String> {
+    let filename = try!(matches.free.get(1).ok_or("The replay command needs an input file".to_string()));
+    let input = try!(try!(read_file(filename)).parse::<Input>().map_err(|e| format!("{}: {}", filename, e)));
+    let expectations = match matches.opt_str("expected") {
+        Some(expected) => try!(parse_expectations(&try!(read_file(&expected)))
+            .map_err(|e| format!("{}: {}", expected, e))),
+        None => Vec::new(),
+    };
+    let ufs = try!(parse_union_finds(matches));
+
+    let mut failed = false;
+    for uf in ufs {
+        println!("Replaying {} site(s) on a {n}x{n} board with {} union-find", input.sites.len(), uf, n=input.n);
+        let replayed = replay::replay(&input, uf, &expectations[..]);
+        match replayed.percolated_at {
+            Some((step, open_sites)) =>
+                println!("Percolates after opening {} site(s), with {} open site(s)", step, open_sites),
+            None => println!("Does not percolate, with {} open site(s)", replayed.open_sites),
+        }
+        for failure in replayed.failures.iter() {
+            println!("{}", failure);
+        }
+        if !replayed.failures.is_empty() {
+            println!("{} of {} expectation(s) failed", replayed.failures.len(), expectations.len());
+            failed = true;
+        } else if !expectations.is_empty() {
+            println!("All {} expectation(s) met", expectations.len());
+        }
+    }
+    if failed {
+        // so that scripted regression checks notice, once every union-find has been replayed
+        io::stdout().flush().ok().expect("Failed to write results to stdout");
+        process::exit(1);
+    }
+    Ok(())
+}
+
+fn parse_nodes(matches: &Matches, name: &str) -> Result<Vec<usize>, String> {
+    match matches.opt_str(name) {
+        Some(nodes) => nodes.split(',').map(|node| node.trim().parse::<usize>()
+            .map_err(|_| format!("Invalid argument for --{}: '{}' is not a node number", name, node))).collect(),
+        None => Ok(Vec::new()),
+    }
+}
+
+fn run_graph(matches: &Matches) -> Result<(), String> {
+    let filename = try!(matches.free.get(1).ok_or("The graph command needs an edge list file".to_string()));
+    let times = try!(parse_opt(matches, "times", 1));
+    let jobs = try!(parse_jobs(matches));
+    let bins = try!(parse_opt(matches, "bins", 10));
+    let seed = try!(parse_opt(matches, "seed", rand::random::<usize>()));
+    let format = try!(parse_opt(matches, "format", Format::Text));
+    let model = try!(parse_opt(matches, "model", Model::Site));
+    let sources = try!(parse_nodes(matches, "sources"));
+    let sinks = try!(parse_nodes(matches, "sinks"));
+    try!(reject_strategy(matches, "graph"));
+    if let Model::Directed(_) = model {
+        return Err("Graphs have no up or down for directed percolation to flow along".to_string());
+    }
+
+    // sources and sinks may come from the file, the command line or both, so only check the graph once it has both
+    let content = try!(read_file(filename));
+    let graph = Arc::new(try!(Graph::parse_with_terminals(&content, &sources, &sinks)
+        .map_err(|e| format!("{}: {}", filename, e))));
+
+    if format == Format::Text {
+        println!("Running {num} {model} percolation(s) on {file} ({nodes} nodes, {edges} edges) using max {jobs} job(s) and seed {seed}",
+            num=times, model=model, file=filename, nodes=graph.nodes, edges=graph.edges.len(), jobs=jobs, seed=seed);
+    }
+    let start = time::precise_time_s();
+    let stats = graph::simulate_graph(graph.clone(), model, times, jobs, seed);
+    let info = GraphRunInfo {
+        graph: filename.clone(),
+        nodes: graph.nodes,
+        edges: graph.edges.len(),
+        model: model,
+        trials: stats.trials(),
+        jobs: jobs,
+        seed: seed,
+        wall_time_s: time::precise_time_s() - start,
+    };
+    output::write_graph_run(&mut io::stdout(), format, &info, &stats, bins)
+        .ok().expect("Failed to write results to stdout");
+    Ok(())
+}
+
+fn run_sweep(matches: &Matches) -> Result<(), String> {
+    let times = try!(parse_opt(matches, "times", 1));
+    let jobs = try!(parse_jobs(matches));
+    let seed = try!(parse_opt(matches, "seed", rand::random::<usize>()));
+    let format = try!(parse_opt(matches, "format", Format::Text));
+    let boundary = try!(parse_opt(matches, "boundary", Boundary::Fixed));
+    let model = try!(parse_model(matches, boundary));
+    if let Model::Directed(_) = model {
+        if !matches.opt_present("nu") {
+            return Err("Directed percolation doesn't share the exponent of 2D percolation, so a sweep of it needs \
+                --nu".to_string());
+        }
+    }
+    let nu = try!(parse_opt(matches, "nu", scaling::NU_2D));
+    let ufs = try!(parse_union_finds(matches));
+    if ufs.len() > 1 {
+        return Err("Only one --uf may be given for a sweep".to_string());
+    }
+    let sizes: Vec<usize> = match matches.opt_str("sizes") {
+        Some(sizes) => try!(sizes.split(',').map(|size| match size.trim().parse::<usize>() {
+            Ok(size) => percolation::check_lattice(&Lattice::square(size).with_boundary(boundary)).map(|_| size)
+                .map_err(|e| format!("Invalid argument for --sizes: {}", e)),
+            _ => Err(format!("Invalid argument for --sizes: '{}' is not a board size", size)),
+        }).collect()),
+        None => vec![16, 32, 64, 128, 256],
+    };
+    // a probability map only fits one size of board, so every size is checked
+    let strategy = try!(parse_strategy(matches, &Lattice::square(sizes[0]).with_boundary(boundary), model));
+    for &size in sizes.iter() {
+        try!(strategy.check(&Lattice::square(size).with_boundary(boundary), model));
+    }
+
+    if format == Format::Text {
+        println!("Running {num} {model} percolation(s) on each of the boards {sizes} with {boundary} boundary, {uf} union-find and {strategy} strategy using max {jobs} job(s) and seed {seed}",
+            num=times, model=model, sizes=sizes.iter().map(|n| n.to_string()).collect::<Vec<String>>().connect(", "),
+            boundary=boundary, uf=ufs[0], strategy=strategy, jobs=jobs, seed=seed);
+    }
+    let sim = Simulation { model: model, union_find: ufs[0], strategy: strategy,
+        ..Simulation::on(Lattice::square(1).with_boundary(boundary)) };
+    let sweep = scaling::sweep(&sim, &sizes[..], nu, times, jobs, seed);
+    output::write_sweep(&mut io::stdout(), format, &sweep).ok().expect("Failed to write results to stdout");
+    Ok(())
+}
+
+fn run_clusters(matches: &Matches) -> Result<(), String> {
+    let times = try!(parse_opt(matches, "times", 1));
+    let jobs = try!(parse_jobs(matches));
+    let seed = try!(parse_opt(matches, "seed", rand::random::<usize>()));
+    let lattice = try!(parse_lattice(matches));
+    let ufs = try!(parse_union_finds(matches));
+    let format = try!(parse_opt(matches, "format", Format::Text));
+    let strategy = try!(parse_strategy(matches, &lattice, Model::Site));
+    if ufs.len() > 1 {
+        return Err("Only one --uf may be given for clusters".to_string());
+    }
+    let mut fractions: Vec<f32> = match matches.opt_str("fractions") {
+        Some(fractions) => try!(fractions.split(',').map(|fraction| match fraction.trim().parse::<f32>() {
+            Ok(fraction) if fraction >= 0.0 && fraction <= 1.0 => Ok(fraction),
+            _ => Err(format!("Invalid argument for --fractions: '{}' is not a fraction between 0 and 1", fraction)),
+        }).collect()),
+        None => (1..10).map(|tenths| tenths as f32 / 10.0).collect(),
+    };
+    fractions.sort_by(|a, b| a.partial_cmp(b).unwrap()); // safe: fractions are never NaN
+
+    if format == Format::Text {
+        println!("Sampling clusters of {num} site percolation(s) on a {lattice} board with {boundary} boundary, {uf} union-find and {strategy} strategy using max {jobs} job(s) and seed {seed}",
+            num=times, lattice=lattice, boundary=lattice.boundary, uf=ufs[0], strategy=strategy, jobs=jobs, seed=seed);
+    }
+    let sim = Simulation { union_find: ufs[0], strategy: strategy.clone(), ..Simulation::on(lattice) };
+    let start = time::precise_time_s();
+    let samples = clusters::simulate_clusters(&sim, &fractions[..], times, jobs, seed);
+    let info = RunInfo {
+        lattice: lattice,
+        model: Model::Site,
+        trials: samples.first().map(|sample| sample.trials).unwrap_or(0),
+        jobs: jobs,
+        seed: seed,
+        union_find: ufs[0],
+        strategy: Some(strategy),
+        wall_time_s: time::precise_time_s() - start,
+    };
+    output::write_clusters(&mut io::stdout(), format, &info, &samples[..]).ok().expect("Failed to write results to stdout");
+    Ok(())
+}
+
+fn run_memory(matches: &Matches) -> Result<(), String> {
+    let lattice = try!(parse_lattice(matches));
+    let ufs = try!(parse_union_finds(matches));
+    let format = try!(parse_opt(matches, "format", Format::Text));
+
+    if format == Format::Text {
+        println!("Memory in bytes of site percolation on a {lattice} board with {boundary} boundary",
+            lattice=lattice, boundary=lattice.boundary);
+    }
+    let usages = ufs.iter().map(|&uf| (uf, MemoryUsage::of(&lattice, uf)))
+        .collect::<Vec<(UnionFindKind, MemoryUsage)>>();
+    output::write_memory(&mut io::stdout(), format, &lattice, &usages[..]).ok().expect("Failed to write results to stdout");
+    Ok(())
+}
+
+fn run_invasion(matches: &Matches) -> Result<(), String> {
+    let times = try!(parse_opt(matches, "times", 1));
+    let jobs = try!(parse_jobs(matches));
+    let bins = try!(parse_opt(matches, "bins", 10));
+    let seed = try!(parse_opt(matches, "seed", rand::random::<usize>()));
+    let lattice = try!(parse_lattice(matches));
+    let ufs = try!(parse_union_finds(matches));
+    let format = try!(parse_opt(matches, "format", Format::Text));
+    try!(reject_strategy(matches, "invasion"));
+    if ufs.len() > 1 {
+        return Err("Only one --uf may be given for invasion".to_string());
+    }
+    if lattice.boundary == Boundary::Torus {
+        return Err("A torus has no top row to invade from".to_string());
+    }
+    if bins == 0 {
+        return Err("Invasion needs at least one bin for its acceptance profile".to_string());
+    }
+
+    if format == Format::Text {
+        println!("Running {num} invasion percolation(s) on a {lattice} board with {boundary} boundary and {uf} union-find using max {jobs} job(s) and seed {seed}",
+            num=times, lattice=lattice, boundary=lattice.boundary, uf=ufs[0], jobs=jobs, seed=seed);
     }
+    let sim = Simulation { union_find: ufs[0], ..Simulation::on(lattice) };
+    let start = time::precise_time_s();
+    let invasions = invasion::simulate_invasions(&sim, times, jobs, seed);
+    let info = RunInfo {
+        lattice: lattice,
+        model: Model::Site,
+        trials: invasions.len(),
+        jobs: jobs,
+        seed: seed,
+        union_find: ufs[0],
+        strategy: None,
+        wall_time_s: time::precise_time_s() - start,
+    };
+    output::write_invasions(&mut io::stdout(), format, &info, &invasions[..], bins)
+        .ok().expect("Failed to write results to stdout");
+    Ok(())
 }
@@ -0,0 +1,559 @@
+use std::f32;
+use std::io::{self, Write};
+use std::str::FromStr;
+use clusters::ClusterSample;
+use curve::PercolationCurve;
+use invasion::{self, Invasion};
+use lattice::Lattice;
+use percolation::{MemoryUsage, Model};
+use scaling::Sweep;
+use stats::PercolationStats;
+use strategy::Strategy;
+use unionfind::UnionFindKind;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Csv,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Format, String> {
+        match s {
+            "text" => Ok(Format::Text),
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("Unknown format '{}' (expected one of: text, csv, json)", s)),
+        }
+    }
+}
+
+/// How a set of trials was run, so that results can be traced back to the command that produced them.
+#[derive(Clone, Debug)]
+pub struct RunInfo {
+    pub lattice: Lattice,
+    pub model: Model,
+    pub trials: usize,
+    pub jobs: u32,
+    pub seed: usize,
+    pub union_find: UnionFindKind,
+    /// None for commands which decide the order elements open in by themselves.
+    pub strategy: Option<Strategy>,
+    pub wall_time_s: f64,
+}
+
+impl RunInfo {
+    /// Names the strategy, or `n/a` if the command doesn't have one.
+    fn strategy_name(&self) -> String {
+        self.strategy.as_ref().map(|strategy| strategy.to_string()).unwrap_or("n/a".to_string())
+    }
+}
+
+/// Writes the results of one run. CSV has one row per trial with the run information and summary statistics
+/// repeated on each row, and JSON has one object per run on a single line, so that several runs can be concatenated.
+pub fn write_run<W: Write>(out: &mut W, format: Format, info: &RunInfo, stats: &PercolationStats, bins: usize,
+        first: bool) -> io::Result<()> {
+    match format {
+        Format::Text => write_text(out, info, stats, bins),
+        Format::Csv => write_csv(out, info, stats, first),
+        Format::Json => write_json(out, info, stats, bins),
+    }
+}
+
+fn write_text<W: Write>(out: &mut W, info: &RunInfo, stats: &PercolationStats, bins: usize) -> io::Result<()> {
+    try!(writeln!(out, "Trials:                  {}", info.trials));
+    try!(writeln!(out, "Wall time:               {:.3}s", info.wall_time_s));
+    write_text_summary(out, stats, bins)
+}
+
+fn write_text_summary<W: Write>(out: &mut W, stats: &PercolationStats, bins: usize) -> io::Result<()> {
+    try!(writeln!(out, "Mean:                    {}", stats.mean()));
+    try!(writeln!(out, "Standard deviation:      {}", stats.stddev()));
+    try!(writeln!(out, "95% confidence interval: [{}, {}]", stats.confidence_lo(), stats.confidence_hi()));
+    try!(writeln!(out, "Min:                     {}", stats.min()));
+    try!(writeln!(out, "Max:                     {}", stats.max()));
+    if bins > 0 && stats.trials() > 0 {
+        try!(writeln!(out, "Histogram of thresholds:"));
+        try!(write!(out, "{}", stats.histogram(bins)));
+    }
+    Ok(())
+}
+
+fn write_csv<W: Write>(out: &mut W, info: &RunInfo, stats: &PercolationStats, header: bool) -> io::Result<()> {
+    if header {
+        try!(writeln!(out, "lattice,boundary,model,trials,jobs,seed,union_find,strategy,wall_time_s,{}",
+                CSV_SUMMARY_HEADER));
+    }
+    let run = format!("{},{},{},{},{},{},{},{},{}", info.lattice, info.lattice.boundary, info.model, info.trials,
+            info.jobs, info.seed, info.union_find, csv_string(&info.strategy_name()), info.wall_time_s);
+    write_csv_rows(out, &run, stats)
+}
+
+const CSV_SUMMARY_HEADER: &'static str = "mean,stddev,confidence_lo,confidence_hi,min,max,trial,threshold";
+
+/// Writes a row per trial, starting with `run` and the summary statistics.
+fn write_csv_rows<W: Write>(out: &mut W, run: &str, stats: &PercolationStats) -> io::Result<()> {
+    let summary = format!("{},{},{},{},{},{}", csv_number(stats.mean()), csv_number(stats.stddev()),
+            csv_number(stats.confidence_lo()), csv_number(stats.confidence_hi()), csv_number(stats.min()),
+            csv_number(stats.max()));
+    for (trial, &threshold) in stats.results().iter().enumerate() {
+        try!(writeln!(out, "{},{},{},{}", run, summary, trial, threshold));
+    }
+    Ok(())
+}
+
+fn write_json<W: Write>(out: &mut W, info: &RunInfo, stats: &PercolationStats, bins: usize) -> io::Result<()> {
+    try!(write!(out, "{{\"lattice\":\"{}\",\"boundary\":\"{}\",\"model\":\"{}\",\"trials\":{},\"jobs\":{},\
+            \"seed\":{},\"union_find\":\"{}\",\"strategy\":\"{}\",\"wall_time_s\":{},",
+            info.lattice, info.lattice.boundary, info.model, info.trials, info.jobs, info.seed, info.union_find,
+            json_string(&info.strategy_name()), info.wall_time_s));
+    write_json_summary(out, stats, bins)
+}
+
+/// Writes the summary statistics and thresholds, and closes the object that the run information was written to.
+fn write_json_summary<W: Write>(out: &mut W, stats: &PercolationStats, bins: usize) -> io::Result<()> {
+    try!(write!(out, "\"mean\":{},\"stddev\":{},\"confidence_lo\":{},\"confidence_hi\":{},\"min\":{},\"max\":{},",
+            json_number(stats.mean()), json_number(stats.stddev()), json_number(stats.confidence_lo()),
+            json_number(stats.confidence_hi()), json_number(stats.min()), json_number(stats.max())));
+    if bins > 0 && stats.trials() > 0 {
+        let histogram = stats.histogram(bins);
+        let counts = histogram.counts.iter().map(|count| count.to_string()).collect::<Vec<String>>();
+        try!(write!(out, "\"histogram\":{{\"lo\":{},\"hi\":{},\"counts\":[{}]}},",
+                json_number(histogram.lo), json_number(histogram.hi), counts.connect(",")));
+    }
+    let thresholds = stats.results().iter().map(|&r| json_number(r)).collect::<Vec<String>>();
+    writeln!(out, "\"thresholds\":[{}]}}", thresholds.connect(","))
+}
+
+/// How a set of trials on a graph was run.
+#[derive(Clone, Debug)]
+pub struct GraphRunInfo {
+    pub graph: String,
+    pub nodes: usize,
+    pub edges: usize,
+    pub model: Model,
+    pub trials: usize,
+    pub jobs: u32,
+    pub seed: usize,
+    pub wall_time_s: f64,
+}
+
+/// Writes the results of a run on a graph, in the same layout as `write_run`.
+pub fn write_graph_run<W: Write>(out: &mut W, format: Format, info: &GraphRunInfo, stats: &PercolationStats,
+        bins: usize) -> io::Result<()> {
+    match format {
+        Format::Text => {
+            try!(writeln!(out, "Trials:                  {}", info.trials));
+            try!(writeln!(out, "Wall time:               {:.3}s", info.wall_time_s));
+            write_text_summary(out, stats, bins)
+        },
+        Format::Csv => {
+            try!(writeln!(out, "graph,nodes,edges,model,trials,jobs,seed,wall_time_s,{}", CSV_SUMMARY_HEADER));
+            let run = format!("{},{},{},{},{},{},{},{}", csv_string(&info.graph), info.nodes, info.edges, info.model, info.trials,
+                    info.jobs, info.seed, info.wall_time_s);
+            write_csv_rows(out, &run, stats)
+        },
+        Format::Json => {
+            try!(write!(out, "{{\"graph\":\"{}\",\"nodes\":{},\"edges\":{},\"model\":\"{}\",\"trials\":{},\"jobs\":{},\
+                    \"seed\":{},\"wall_time_s\":{},", json_string(&info.graph), info.nodes,
+                    info.edges, info.model, info.trials, info.jobs, info.seed, info.wall_time_s));
+            write_json_summary(out, stats, bins)
+        },
+    }
+}
+
+/// Writes a percolation curve as a table with a row per number of open elements. The fraction column is that number
+/// over the number of elements, not an open probability p.
+pub fn write_curve<W: Write>(out: &mut W, format: Format, info: &RunInfo, curve: &PercolationCurve) -> io::Result<()> {
+    match format {
+        Format::Text => {
+            try!(writeln!(out, "{:>10} {:>10} {:>14} {:>16}", "opened", "of all", "P(percolates)", "largest cluster"));
+            for k in 0 .. curve.len() {
+                try!(writeln!(out, "{:>10} {:>10.6} {:>14.6} {:>16.6}", k, curve.opened_fraction(k),
+                        curve.percolation_probability(k), curve.largest_cluster_fraction(k)));
+            }
+            Ok(())
+        },
+        Format::Csv => {
+            try!(writeln!(out, "open_elements,opened_fraction,percolation_probability,largest_cluster_fraction"));
+            for k in 0 .. curve.len() {
+                try!(writeln!(out, "{},{},{},{}", k, csv_number(curve.opened_fraction(k)),
+                        csv_number(curve.percolation_probability(k)), csv_number(curve.largest_cluster_fraction(k))));
+            }
+            Ok(())
+        },
+        Format::Json => {
+            let column = |value: &Fn(usize) -> f32| {
+                (0 .. curve.len()).map(|k| json_number(value(k))).collect::<Vec<String>>().connect(",")
+            };
+            try!(write!(out, "{{\"lattice\":\"{}\",\"boundary\":\"{}\",\"model\":\"{}\",\"trials\":{},\"jobs\":{},\
+                    \"seed\":{},\"union_find\":\"{}\",\"strategy\":\"{}\",\"wall_time_s\":{},",
+                    info.lattice, info.lattice.boundary, info.model, info.trials, info.jobs, info.seed, info.union_find,
+                    json_string(&info.strategy_name()), info.wall_time_s));
+            writeln!(out, "\"opened_fraction\":[{}],\"percolation_probability\":[{}],\
+                    \"largest_cluster_fraction\":[{}]}}",
+                    column(&|k| curve.opened_fraction(k)), column(&|k| curve.percolation_probability(k)),
+                    column(&|k| curve.largest_cluster_fraction(k)))
+        },
+    }
+}
+
+/// Writes the thresholds of each size in a sweep, followed by the extrapolated threshold. CSV has a row per size with
+/// the fit repeated on each row, and JSON is a single object.
+pub fn write_sweep<W: Write>(out: &mut W, format: Format, sweep: &Sweep) -> io::Result<()> {
+    let (p_c, p_c_error) = sweep.fit.map(|fit| (fit.p_c as f32, fit.p_c_error as f32)).unwrap_or((f32::NAN, f32::NAN));
+    match format {
+        Format::Text => {
+            try!(writeln!(out, "{:>6} {:>7} {:>10} {:>10} {:>10} {:>10}", "n", "trials", "mean", "stddev",
+                    "95% lo", "95% hi"));
+            for &(n, ref stats) in sweep.sizes.iter() {
+                try!(writeln!(out, "{:>6} {:>7} {:>10.6} {:>10.6} {:>10.6} {:>10.6}", n, stats.trials(), stats.mean(),
+                        stats.stddev(), stats.confidence_lo(), stats.confidence_hi()));
+            }
+            match sweep.fit {
+                Some(fit) => writeln!(out, "Extrapolated threshold:  {} ± {} (fitted with ν = {})", fit.p_c,
+                        fit.p_c_error, sweep.nu),
+                None => writeln!(out, "Extrapolated threshold:  needs at least two different sizes"),
+            }
+        },
+        Format::Csv => {
+            try!(writeln!(out, "boundary,model,union_find,strategy,jobs,seed,nu,p_c,p_c_error,\
+                    n,trials,mean,stddev,confidence_lo,confidence_hi"));
+            for &(n, ref stats) in sweep.sizes.iter() {
+                try!(writeln!(out, "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}", sweep.sim.lattice.boundary,
+                        sweep.sim.model, sweep.sim.union_find, csv_string(&sweep.sim.strategy.to_string()), sweep.jobs,
+                        sweep.seed, sweep.nu, csv_number(p_c), csv_number(p_c_error), n, stats.trials(),
+                        csv_number(stats.mean()), csv_number(stats.stddev()), csv_number(stats.confidence_lo()),
+                        csv_number(stats.confidence_hi())));
+            }
+            Ok(())
+        },
+        Format::Json => {
+            let sizes = sweep.sizes.iter().map(|&(n, ref stats)| {
+                format!("{{\"n\":{},\"trials\":{},\"mean\":{},\"stddev\":{},\"confidence_lo\":{},\"confidence_hi\":{}}}",
+                        n, stats.trials(), json_number(stats.mean()), json_number(stats.stddev()),
+                        json_number(stats.confidence_lo()), json_number(stats.confidence_hi()))
+            }).collect::<Vec<String>>();
+            writeln!(out, "{{\"boundary\":\"{}\",\"model\":\"{}\",\"union_find\":\"{}\",\"strategy\":\"{}\",\
+                    \"jobs\":{},\"seed\":{},\"nu\":{},\"p_c\":{},\"p_c_error\":{},\"sizes\":[{}]}}",
+                    sweep.sim.lattice.boundary, sweep.sim.model, sweep.sim.union_find,
+                    json_string(&sweep.sim.strategy.to_string()), sweep.jobs, sweep.seed, sweep.nu, json_number(p_c),
+                    json_number(p_c_error), sizes.connect(","))
+        },
+    }
+}
+
+/// Writes the bytes of heap memory taken up by each part of a site percolation system on `lattice`, for each
+/// union-find. CSV and JSON have a row or object per union-find.
+pub fn write_memory<W: Write>(out: &mut W, format: Format, lattice: &Lattice, usages: &[(UnionFindKind, MemoryUsage)])
+        -> io::Result<()> {
+    match format {
+        Format::Text => {
+            try!(writeln!(out, "{:<20} {:>12} {:>12} {:>12} {:>12} {:>12} {:>12}", "union-find", "grid", "qu",
+                    "clusters", "full roots", "wrapping", "total"));
+            for &(uf, usage) in usages {
+                try!(writeln!(out, "{:<20} {:>12} {:>12} {:>12} {:>12} {:>12} {:>12}", uf, usage.grid, usage.qu,
+                        usage.clusters, usage.full_roots, usage.wrapping, usage.total()));
+            }
+            Ok(())
+        },
+        Format::Csv => {
+            try!(writeln!(out, "lattice,boundary,union_find,grid,qu,clusters,full_roots,wrapping,total"));
+            for &(uf, usage) in usages {
+                try!(writeln!(out, "{},{},{},{},{},{},{},{},{}", lattice, lattice.boundary, uf, usage.grid, usage.qu,
+                        usage.clusters, usage.full_roots, usage.wrapping, usage.total()));
+            }
+            Ok(())
+        },
+        Format::Json => {
+            for &(uf, usage) in usages {
+                try!(writeln!(out, "{{\"lattice\":\"{}\",\"boundary\":\"{}\",\"union_find\":\"{}\",\"grid\":{},\"qu\":{},\
+                        \"clusters\":{},\"full_roots\":{},\"wrapping\":{},\"total\":{}}}", lattice, lattice.boundary, uf,
+                        usage.grid, usage.qu, usage.clusters, usage.full_roots, usage.wrapping, usage.total()));
+            }
+            Ok(())
+        },
+    }
+}
+
+/// Writes cluster statistics averaged over trials, with a row per open site fraction. Only JSON includes the whole
+/// size distribution, as an array whose entry `s` is the mean number of clusters of `s` sites.
+pub fn write_clusters<W: Write>(out: &mut W, format: Format, info: &RunInfo, samples: &[ClusterSample])
+        -> io::Result<()> {
+    match format {
+        Format::Text => {
+            try!(writeln!(out, "{:>10} {:>12} {:>12} {:>24}", "fraction", "clusters", "largest",
+                    "mean excluding largest"));
+            for sample in samples {
+                try!(writeln!(out, "{:>10.4} {:>12.3} {:>12.3} {:>24.3}", sample.fraction, sample.count(),
+                        sample.largest(), sample.mean_excluding_largest()));
+            }
+            Ok(())
+        },
+        Format::Csv => {
+            try!(writeln!(out, "fraction,trials,clusters,largest,mean_excluding_largest"));
+            for sample in samples {
+                try!(writeln!(out, "{},{},{},{},{}", sample.fraction, sample.trials, csv_number(sample.count() as f32),
+                        csv_number(sample.largest() as f32), csv_number(sample.mean_excluding_largest() as f32)));
+            }
+            Ok(())
+        },
+        Format::Json => {
+            try!(write!(out, "{{\"lattice\":\"{}\",\"boundary\":\"{}\",\"model\":\"{}\",\"trials\":{},\"jobs\":{},\
+                    \"seed\":{},\"union_find\":\"{}\",\"strategy\":\"{}\",\"wall_time_s\":{},",
+                    info.lattice, info.lattice.boundary, info.model, info.trials, info.jobs, info.seed, info.union_find,
+                    json_string(&info.strategy_name()), info.wall_time_s));
+            let samples = samples.iter().map(|sample| {
+                let distribution = sample.distribution().iter().map(|&clusters| json_number(clusters as f32))
+                    .collect::<Vec<String>>();
+                format!("{{\"fraction\":{},\"clusters\":{},\"largest\":{},\"mean_excluding_largest\":{},\
+                        \"distribution\":[{}]}}", json_number(sample.fraction), json_number(sample.count() as f32),
+                        json_number(sample.largest() as f32), json_number(sample.mean_excluding_largest() as f32),
+                        distribution.connect(","))
+            }).collect::<Vec<String>>();
+            writeln!(out, "\"samples\":[{}]}}", samples.connect(","))
+        },
+    }
+}
+
+/// Writes the results of invasions. Text summarises the invaded fractions and the acceptance profile pooled over
+/// every trial. CSV lists the invaded sites of every trial in the order they were invaded, with their coordinates and
+/// strengths, and JSON has both the summary and the order of invasion of each trial.
+pub fn write_invasions<W: Write>(out: &mut W, format: Format, info: &RunInfo, invasions: &[Invasion], bins: usize)
+        -> io::Result<()> {
+    let fractions = PercolationStats::new(invasions.iter().map(|invasion| invasion.invaded_fraction()).collect());
+    let profile = invasion::pooled_acceptance_profile(invasions, bins);
+    match format {
+        Format::Text => {
+            try!(writeln!(out, "Trials:                  {}", info.trials));
+            try!(writeln!(out, "Wall time:               {:.3}s", info.wall_time_s));
+            try!(writeln!(out, "Mean invaded fraction:   {}", fractions.mean()));
+            try!(writeln!(out, "Standard deviation:      {}", fractions.stddev()));
+            try!(writeln!(out, "Acceptance profile:"));
+            for (bin, &accepted) in profile.iter().enumerate() {
+                try!(writeln!(out, "[{:.4}, {:.4}) {:.4}", bin as f32 / bins as f32, (bin + 1) as f32 / bins as f32,
+                        accepted));
+            }
+            Ok(())
+        },
+        Format::Csv => {
+            try!(writeln!(out, "trial,step,site,row,col,layer,strength"));
+            for (trial, invasion) in invasions.iter().enumerate() {
+                for (step, &site) in invasion.order.iter().enumerate() {
+                    let (row, col, layer) = invasion.lattice.coordinates(site);
+                    try!(writeln!(out, "{},{},{},{},{},{},{}", trial, step, site, row, col, layer,
+                            invasion.strengths[site]));
+                }
+            }
+            Ok(())
+        },
+        Format::Json => {
+            try!(write!(out, "{{\"lattice\":\"{}\",\"boundary\":\"{}\",\"trials\":{},\"jobs\":{},\"seed\":{},\
+                    \"union_find\":\"{}\",\"wall_time_s\":{},", info.lattice, info.lattice.boundary, info.trials,
+                    info.jobs, info.seed, info.union_find, info.wall_time_s));
+            let profile = profile.iter().map(|&accepted| json_number(accepted)).collect::<Vec<String>>();
+            let orders = invasions.iter().map(|invasion| {
+                format!("[{}]", invasion.order.iter().map(|site| site.to_string()).collect::<Vec<String>>().connect(","))
+            }).collect::<Vec<String>>();
+            writeln!(out, "\"mean_invaded_fraction\":{},\"acceptance_profile\":[{}],\"orders\":[{}]}}",
+                    json_number(fractions.mean()), profile.connect(","), orders.connect(","))
+        },
+    }
+}
+
+/// Formats a number for CSV, leaving undefined values (such as the standard deviation of a single trial) empty.
+fn csv_number(x: f32) -> String {
+    if x.is_finite() { x.to_string() } else { String::new() }
+}
+
+/// Quotes a string, such as a file name, for CSV if it contains a comma, quote or line break, doubling its quotes.
+fn csv_string(s: &str) -> String {
+    if s.chars().any(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", s.replace("\"", "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+/// Escapes a string, such as a file name, to go between quotes in JSON, which doesn't allow control characters there.
+fn json_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            '\u{8}' => escaped.push_str("\\b"),
+            '\u{c}' => escaped.push_str("\\f"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Formats a number for JSON, which has no representation for NaN or infinities.
+fn json_number(x: f32) -> String {
+    if x.is_finite() { x.to_string() } else { "null".to_string() }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use super::{Format, GraphRunInfo, RunInfo, write_curve, write_graph_run, write_memory, write_run, write_sweep};
+    use curve;
+    use lattice::Lattice;
+    use percolation::{MemoryUsage, Model, Simulation, trial_rng};
+    use scaling::{self, Sweep};
+    use stats::PercolationStats;
+    use strategy::{ProbabilityMap, Strategy};
+    use unionfind::UnionFindKind;
+
+    fn info() -> RunInfo {
+        RunInfo {
+            lattice: Lattice::rectangle(10, 20),
+            model: Model::Bond,
+            trials: 2,
+            jobs: 1,
+            seed: 7,
+            union_find: UnionFindKind::WeightedQuickUnion,
+            strategy: Some(Strategy::Uniform),
+            wall_time_s: 0.5,
+        }
+    }
+
+    fn written(format: Format, stats: &PercolationStats) -> String {
+        let mut out = Vec::new();
+        write_run(&mut out, format, &info(), stats, 2, true).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn csv_has_a_row_per_trial() {
+        let csv = written(Format::Csv, &PercolationStats::new(vec![0.5, 0.75]));
+        let lines = csv.lines().collect::<Vec<&str>>();
+
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].ends_with(",trial,threshold"));
+        assert!(lines[1].starts_with("10x20,fixed,bond,2,1,7,weighted,uniform,0.5,0.625,"));
+        assert!(lines[1].ends_with(",0,0.5"));
+        assert!(lines[2].ends_with(",1,0.75"));
+
+        // map file names can have commas in them, and commands without a strategy say so
+        let map = ProbabilityMap::parse("maps/a,b.txt", "1 1\n1 1\n").unwrap();
+        let mut out = Vec::new();
+        write_run(&mut out, Format::Csv, &RunInfo { strategy: Some(Strategy::Map(Arc::new(map))), ..info() },
+            &PercolationStats::new(vec![0.5]), 2, false).unwrap();
+        let csv = String::from_utf8(out).unwrap();
+        assert!(csv.starts_with("10x20,fixed,bond,2,1,7,weighted,\"map:maps/a,b.txt\",0.5,"));
+        let mut out = Vec::new();
+        write_run(&mut out, Format::Csv, &RunInfo { strategy: None, ..info() }, &PercolationStats::new(vec![0.5]), 2,
+            false).unwrap();
+        assert!(String::from_utf8(out).unwrap().starts_with("10x20,fixed,bond,2,1,7,weighted,n/a,0.5,"));
+    }
+
+    #[test]
+    fn json_is_a_single_line_object() {
+        let json = written(Format::Json, &PercolationStats::new(vec![0.5, 0.75]));
+
+        assert!(json.starts_with("{\"lattice\":\"10x20\",\"boundary\":\"fixed\",\"model\":\"bond\",\"trials\":2,\"jobs\":1,\"seed\":7,\
+                \"union_find\":\"weighted\",\"strategy\":\"uniform\","));
+        assert!(json.contains("\"histogram\":{\"lo\":0.5,\"hi\":0.75,\"counts\":[1,1]},"));
+        assert!(json.ends_with("\"thresholds\":[0.5,0.75]}\n"));
+    }
+
+    #[test]
+    fn undefined_statistics_are_null_in_json() {
+        let json = written(Format::Json, &PercolationStats::new(vec![0.5]));
+
+        assert!(json.contains("\"stddev\":null,"));
+    }
+
+    #[test]
+    fn curve_csv_has_a_row_per_number_of_open_sites() {
+        let mut curve = curve::PercolationCurve::new(&Simulation::new(2));
+        curve.add(&curve::trace(&Simulation::new(2), &mut trial_rng(0, 0))[..]);
+        let mut out = Vec::new();
+        write_curve(&mut out, Format::Csv, &info(), &curve).unwrap();
+        let csv = String::from_utf8(out).unwrap();
+        let lines = csv.lines().collect::<Vec<&str>>();
+
+        assert_eq!(lines.len(), 6);
+        assert!(lines[0].starts_with("open_elements,opened_fraction,"));
+        assert_eq!(lines[1], "0,0,0,0");
+        assert_eq!(lines[5], "4,1,1,1");
+    }
+
+    #[test]
+    fn graph_runs_have_their_own_columns() {
+        let info = GraphRunInfo {
+            graph: "net.txt".to_string(),
+            nodes: 5,
+            edges: 4,
+            model: Model::Site,
+            trials: 1,
+            jobs: 1,
+            seed: 3,
+            wall_time_s: 0.25,
+        };
+        let mut out = Vec::new();
+        write_graph_run(&mut out, Format::Csv, &info, &PercolationStats::new(vec![0.6]), 2).unwrap();
+        let csv = String::from_utf8(out).unwrap();
+        let lines = csv.lines().collect::<Vec<&str>>();
+
+        assert_eq!(lines[0], "graph,nodes,edges,model,trials,jobs,seed,wall_time_s,\
+                mean,stddev,confidence_lo,confidence_hi,min,max,trial,threshold");
+        assert_eq!(lines[1], "net.txt,5,4,site,1,1,3,0.25,0.6,,,,0.6,0.6,0,0.6");
+
+        // a comma in the file name mustn't split its column
+        let mut out = Vec::new();
+        write_graph_run(&mut out, Format::Csv, &GraphRunInfo { graph: "nets/a,\"b\".txt".to_string(), ..info },
+            &PercolationStats::new(vec![0.6]), 2).unwrap();
+        assert!(String::from_utf8(out).unwrap().lines().nth(1).unwrap().starts_with("\"nets/a,\"\"b\"\".txt\",5,"));
+
+        // nor may control characters end up raw inside a JSON string
+        let mut out = Vec::new();
+        write_graph_run(&mut out, Format::Json, &GraphRunInfo { graph: "a\\\"b\nc\td\u{1}.txt".to_string(), ..info },
+            &PercolationStats::new(vec![0.6]), 2).unwrap();
+        assert!(String::from_utf8(out).unwrap().contains("\"graph\":\"a\\\\\\\"b\\nc\\td\\u0001.txt\","));
+    }
+
+    #[test]
+    fn sweep_json_lists_each_size_and_the_fit() {
+        let sizes = vec![(16, PercolationStats::new(vec![0.6])), (32, PercolationStats::new(vec![0.595]))];
+        let sweep = Sweep {
+            sim: Simulation::new(1),
+            seed: 2,
+            jobs: 1,
+            nu: 1.5,
+            fit: scaling::fit_threshold(&sizes, 1.5),
+            sizes: sizes,
+        };
+        let mut out = Vec::new();
+        write_sweep(&mut out, Format::Json, &sweep).unwrap();
+        let json = String::from_utf8(out).unwrap();
+
+        assert!(json.starts_with("{\"boundary\":\"fixed\",\"model\":\"site\",\"union_find\":\"weighted\",\
+                \"strategy\":\"uniform\",\"jobs\":1,\"seed\":2,\"nu\":1.5,\"p_c\":0.5"));
+        assert!(json.contains(",\"p_c_error\":null,\"sizes\":[{\"n\":16,\"trials\":1,\"mean\":0.6,\"stddev\":null,"));
+    }
+
+    #[test]
+    fn memory_csv_has_a_row_per_union_find() {
+        let usage = MemoryUsage { grid: 16, qu: 40, clusters: 32, full_roots: 16, wrapping: 0 };
+        let mut out = Vec::new();
+        write_memory(&mut out, Format::Csv, &Lattice::square(8),
+            &[(UnionFindKind::QuickFind, usage), (UnionFindKind::WeightedQuickUnion, usage)]).unwrap();
+        let csv = String::from_utf8(out).unwrap();
+        let lines = csv.lines().collect::<Vec<&str>>();
+
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[1], "8x8,fixed,quick-find,16,40,32,16,0,104");
+        assert!(lines[2].starts_with("8x8,fixed,weighted,"));
+    }
+}
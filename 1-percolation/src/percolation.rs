@@ -1,135 +1,735 @@
+use std::cmp;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
 use std::iter;
-use conversions::{AsUsizeConverter, TryU32Converter};
-use std::num::Int;
-use unionfind::{UnionFind, WeightedQuickUnionUF};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use bitset::BitSet;
+use bond::BondPercolation;
+use clusters::ClusterStats;
+use conversions::AsUsizeConverter;
+use directed::{DirectedPercolation, Orientation};
+use lattice::{Boundary, Lattice};
+use progress::{self, Progress};
+use rand::{Rng, SeedableRng, StdRng};
+use stats::{PercolationStats, RunningStats};
+use strategy::{self, Opener, Strategy};
+use unionfind::{UnionFind, UnionFindKind};
+use wrapping::{Wrapping, WrappingUF};
 
+/// A grid of sites on a `Lattice`. Sites of 2D lattices are addressed as `(i, j)` = (row, column) from (1, 1) at the
+/// top left, so `i == 1` is the top row, as in the course spec and its sample input files; any site can also be
+/// addressed by its index in the lattice.
 pub struct Percolation {
-    n: usize,
-    grid: Vec<bool>,
-    qu: WeightedQuickUnionUF,
+    lattice: Lattice,
+    grid: BitSet,
+    open_sites: usize,
+    // connects open sites to each other as well as to a virtual top and a virtual bottom node, to check percolation
+    qu: Box<UnionFind>,
+    // connects open sites to each other only, so that cluster sizes don't include the virtual nodes (which would
+    // also join up every cluster touching the top or bottom row)
+    clusters: Box<UnionFind>,
+    // whether each cluster in `clusters`, indexed by its root, contains a site in the top row. Unlike checking for
+    // a connection to the virtual top in `qu`, this does not suffer from backwash through the bottom row once the
+    // system percolates
+    full_roots: BitSet,
+    // tracks clusters wrapping around periodic lattices, which have no virtual nodes to connect
+    wrapping: Option<WrappingUF>,
+}
+
+/// The number of bytes of heap memory each part of a `Percolation` takes up.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// Whether each site is open, a bit per site.
+    pub grid: usize,
+    /// The union-find with the virtual top and bottom.
+    pub qu: usize,
+    /// The union-find of the open clusters.
+    pub clusters: usize,
+    /// Whether each cluster is full, a bit per site.
+    pub full_roots: usize,
+    /// The union-find tracking wrapping clusters, only on periodic lattices.
+    pub wrapping: usize,
+}
+
+impl MemoryUsage {
+    /// Returns the memory a `Percolation` on `lattice` with `uf` union-finds would take up, without creating one.
+    pub fn of(lattice: &Lattice, uf: UnionFindKind) -> MemoryUsage {
+        let sites = lattice.site_count();
+        MemoryUsage {
+            grid: BitSet::memory_usage_of(sites),
+            qu: uf.memory_usage_of(sites + 2),
+            clusters: uf.memory_usage_of(sites),
+            full_roots: BitSet::memory_usage_of(sites),
+            wrapping: if lattice.boundary == Boundary::Fixed { 0 } else { WrappingUF::memory_usage_of(sites) },
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.grid + self.qu + self.clusters + self.full_roots + self.wrapping
+    }
+}
+
+/// Why a `Percolation` couldn't be created or queried.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PercolationError {
+    /// The lattice has no sites.
+    Empty,
+    /// The lattice has more sites than can be numbered.
+    TooLarge(Lattice),
+    /// The lattice is a torus with a single row, which no cluster can wrap around vertically.
+    NeverPercolates(Lattice),
+    /// The (row, column) isn't on the lattice.
+    OutOfBounds { i: usize, j: usize, lattice: Lattice },
+}
+
+impl fmt::Display for PercolationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PercolationError::Empty => write!(f, "A percolation lattice needs at least one site"),
+            PercolationError::TooLarge(lattice) =>
+                write!(f, "A {} lattice has too many sites to number", lattice),
+            PercolationError::NeverPercolates(lattice) =>
+                write!(f, "A {} torus has a single row, so it can never percolate by wrapping around it", lattice),
+            PercolationError::OutOfBounds { i, j, lattice } =>
+                write!(f, "Out of bounds: ({}, {}) on a {} lattice", i, j, lattice),
+        }
+    }
+}
+
+impl Error for PercolationError {
+    fn description(&self) -> &str {
+        match *self {
+            PercolationError::Empty => "empty lattice",
+            PercolationError::TooLarge(_) => "lattice too large",
+            PercolationError::NeverPercolates(_) => "lattice never percolates",
+            PercolationError::OutOfBounds { .. } => "site out of bounds",
+        }
+    }
+}
+
+/// Checks that a system can be built on `lattice`: it needs some sites, and few enough to number them (and a couple
+/// of virtual nodes) with a usize. The union-finds number them with u32 when they fit and u64 otherwise. A torus
+/// percolates by wrapping around vertically, which a single row never does, so it needs at least two rows.
+pub fn check_lattice(lattice: &Lattice) -> Result<(), PercolationError> {
+    let sites = lattice.rows.checked_mul(lattice.cols).and_then(|sites| sites.checked_mul(lattice.layers));
+    match sites {
+        Some(0) => Err(PercolationError::Empty),
+        Some(_) if lattice.boundary == Boundary::Torus && lattice.rows == 1 =>
+            Err(PercolationError::NeverPercolates(*lattice)),
+        Some(sites) if sites.checked_add(2).is_some() => Ok(()),
+        _ => Err(PercolationError::TooLarge(*lattice)),
+    }
 }
 
 impl Percolation {
     pub fn new(n: usize) -> Percolation {
+        Percolation::with_union_find(n, UnionFindKind::WeightedQuickUnion)
+    }
+
+    pub fn with_union_find(n: usize, uf: UnionFindKind) -> Percolation {
+        Percolation::with_lattice(Lattice::square(n), uf)
+    }
+
+    pub fn with_lattice(lattice: Lattice, uf: UnionFindKind) -> Percolation {
+        let sites = lattice.site_count();
         Percolation {
-            n: n,
-            grid: iter::repeat(false).take(n.pow(2)).collect(),
-            qu: {
-                let mut q = WeightedQuickUnionUF::new((n * n + 2).try_u32());
-                for i in 1 .. (n + 1) {
-                    q.union((i - 1).try_u32(), (n * n).try_u32());
-                    q.union((n * (n - 1) + i - 1).try_u32(), (n * n + 1).try_u32());
-                }
-                q
-            },
+            lattice: lattice,
+            grid: BitSet::new(sites),
+            open_sites: 0,
+            qu: uf.create(sites + 2),
+            clusters: uf.create(sites),
+            full_roots: BitSet::new(sites),
+            wrapping: if lattice.boundary == Boundary::Fixed { None } else { Some(WrappingUF::new(sites)) },
+        }
+    }
+
+    /// Like `new`, but returns an error rather than panicking if the grid is empty or too large.
+    pub fn try_new(n: usize) -> Result<Percolation, PercolationError> {
+        Percolation::try_with_lattice(Lattice::square(n), UnionFindKind::WeightedQuickUnion)
+    }
+
+    /// Like `with_lattice`, but returns an error rather than panicking if the lattice is empty or too large.
+    pub fn try_with_lattice(lattice: Lattice, uf: UnionFindKind) -> Result<Percolation, PercolationError> {
+        try!(check_lattice(&lattice));
+        Ok(Percolation::with_lattice(lattice, uf))
+    }
+
+    pub fn lattice(&self) -> &Lattice {
+        &self.lattice
+    }
+
+    pub fn memory_usage(&self) -> MemoryUsage {
+        MemoryUsage {
+            grid: self.grid.memory_usage(),
+            qu: self.qu.memory_usage(),
+            clusters: self.clusters.memory_usage(),
+            full_roots: self.full_roots.memory_usage(),
+            wrapping: self.wrapping.as_ref().map(|wrapping| wrapping.memory_usage()).unwrap_or(0),
         }
     }
 
-    fn in_bounds(&self, i: usize, j: usize) -> bool {
-        i >= 1 && i <= self.n && j >= 1 && j <= self.n
+    fn virtual_top(&self) -> usize {
+        self.lattice.site_count()
+    }
+
+    fn virtual_bottom(&self) -> usize {
+        self.lattice.site_count() + 1
+    }
+
+    fn try_index(&self, i: usize, j: usize) -> Result<usize, PercolationError> {
+        self.lattice.site(i, j, 1).ok_or(PercolationError::OutOfBounds { i: i, j: j, lattice: self.lattice })
     }
 
     fn to_index(&self, i: usize, j: usize) -> usize {
-        return (i - 1) + (j - 1) * self.n
+        match self.try_index(i, j) {
+            Ok(site) => site,
+            Err(e) => panic!(e.to_string()),
+        }
     }
 
-    fn assert_in_bounds(&self, i: usize, j: usize) {
-        if !self.in_bounds(i, j) {
-            panic!(format!("Out of bounds: ({i}, {j}) with n = {n}",
-                    i=i, j=j, n=self.n))
+    fn assert_site_in_bounds(&self, site: usize) {
+        if site >= self.lattice.site_count() {
+            panic!(format!("Out of bounds: site {site} on a {lattice} lattice", site=site, lattice=self.lattice))
         }
     }
 
     pub fn open(&mut self, i: usize, j: usize) {
-        self.assert_in_bounds(i, j);
-        let index = self.to_index(i, j);
-        self.grid[index] = true;
-
-        let neighbours = vec![(i, j - 1), (i, j + 1), (i + 1, j), (i - 1, j)];
-        for (ni, nj) in neighbours {
-            if self.in_bounds(ni, nj) && self.is_open(ni, nj) {
-                // println!("Connecting newly opened {:?} with already-open {:?}", (i, j), (ni, nj));
-                let neighbour_index = self.to_index(ni, nj);
-                self.qu.union(index.try_u32(), neighbour_index.try_u32());
+        let site = self.to_index(i, j);
+        self.open_site(site);
+    }
+
+    pub fn is_open(&self, i: usize, j: usize) -> bool {
+        self.is_open_site(self.to_index(i, j))
+    }
+
+    /// A full site is an open site that is connected to the top row through a chain of open sites.
+    pub fn is_full(&self, i: usize, j: usize) -> bool {
+        self.is_full_site(self.to_index(i, j))
+    }
+
+    /// Like `open`, but returns an error rather than panicking if (i, j) is out of bounds.
+    pub fn try_open(&mut self, i: usize, j: usize) -> Result<(), PercolationError> {
+        let site = try!(self.try_index(i, j));
+        self.open_site(site);
+        Ok(())
+    }
+
+    /// Like `is_open`, but returns an error rather than panicking if (i, j) is out of bounds.
+    pub fn try_is_open(&self, i: usize, j: usize) -> Result<bool, PercolationError> {
+        self.try_index(i, j).map(|site| self.is_open_site(site))
+    }
+
+    /// Like `is_full`, but returns an error rather than panicking if (i, j) is out of bounds.
+    pub fn try_is_full(&self, i: usize, j: usize) -> Result<bool, PercolationError> {
+        self.try_index(i, j).map(|site| self.is_full_site(site))
+    }
+
+    pub fn open_site(&mut self, site: usize) {
+        self.assert_site_in_bounds(site);
+        if self.grid.get(site) {
+            return;
+        }
+        self.grid.set(site, true);
+        self.open_sites += 1;
+
+        if self.lattice.is_top(site) {
+            let top = self.virtual_top();
+            self.qu.union(site, top);
+            // the site was blocked until now, so it's on its own in `clusters`
+            self.full_roots.set(site, true);
+        }
+        if self.lattice.is_bottom(site) {
+            let bottom = self.virtual_bottom();
+            self.qu.union(site, bottom);
+        }
+
+        for (neighbour, step) in self.lattice.steps(site) {
+            if self.grid.get(neighbour) {
+                self.qu.union(site, neighbour);
+                self.join_clusters(site, neighbour);
+                if let Some(ref mut wrapping) = self.wrapping {
+                    wrapping.union(site, neighbour, step);
+                }
             }
         }
     }
 
-    pub fn is_open(&self, i: usize, j: usize) -> bool {
-        self.assert_in_bounds(i, j);
-        self.grid[self.to_index(i, j)]
+    fn join_clusters(&mut self, p: usize, q: usize) {
+        let (i, j) = (self.clusters.find(p), self.clusters.find(q));
+        if i != j {
+            let full = self.full_roots.get(i) || self.full_roots.get(j);
+            self.clusters.union(i, j);
+            let root = self.clusters.find(i);
+            self.full_roots.set(root, full);
+        }
     }
 
-    pub fn percolates(&self) -> bool {
-        self.qu.connected((self.n * self.n).try_u32(), (self.n * self.n + 1).try_u32())
+    pub fn is_open_site(&self, site: usize) -> bool {
+        self.assert_site_in_bounds(site);
+        self.grid.get(site)
     }
-}
 
-pub fn simulate(n: usize) -> f32 {
-    use rand::{self, Rng};
+    pub fn is_full_site(&self, site: usize) -> bool {
+        self.is_open_site(site) && self.full_roots.get(self.clusters.find(site))
+    }
+
+    pub fn number_of_open_sites(&self) -> usize {
+        self.open_sites
+    }
 
-    let mut to_open: Vec<(usize, usize)> = Vec::with_capacity(n * n);
-    for i in 1 .. (n + 1) {
-        for j in 1 .. (n + 1) {
-            to_open.push((i, j));
+    /// Returns the number of open sites in the cluster containing `site`, or 0 if it is blocked.
+    pub fn cluster_size_of_site(&self, site: usize) -> usize {
+        if self.is_open_site(site) {
+            self.clusters.component_size(site)
+        } else {
+            0
         }
     }
-    rand::thread_rng().shuffle(to_open.as_mut_slice());
 
-    let mut perc = Percolation::new(n);
-    while !perc.percolates() {
-        let (i, j) = to_open.pop().unwrap(); // safe: system must percolate before we run out of sites to open
-        perc.open(i, j);
+    /// Returns the number of open sites in each open cluster, in no particular order.
+    pub fn cluster_sizes(&self) -> Vec<usize> {
+        (0 .. self.lattice.site_count())
+            .filter(|&site| self.grid.get(site) && self.clusters.find(site) == site)
+            .map(|root| self.clusters.component_size(root))
+            .collect()
+    }
+
+    /// Returns the distribution of open cluster sizes, along with the largest, the mean cluster size S excluding the
+    /// largest and the number of clusters.
+    pub fn cluster_stats(&self) -> ClusterStats {
+        ClusterStats::from_sizes(&self.cluster_sizes())
     }
-    (n*n - to_open.len()) as f32 / (n * n) as f32
-}
 
-pub fn simulate_multiple(n: usize, times: usize, jobs: u32) -> PercolationStats {
-    PercolationStats {
-        results: if jobs == 1 {
-            (0..times).map(|_| simulate(n)).collect()
+    /// On a torus, which has no top or bottom row, the system percolates once a cluster wraps around vertically.
+    pub fn percolates(&self) -> bool {
+        if self.lattice.boundary == Boundary::Torus {
+            self.wrapping().vertically
         } else {
-            use std::thread;
-            use std::sync::{Arc, Mutex, mpsc};
-            use std::cmp;
-
-            // simulation runs left. Data doesn't matter - it's only used to distribute work across jobs.
-            let sims_left = Arc::new(Mutex::new((0..times).collect::<Vec<usize>>()));
-
-            let (tx, rx) = mpsc :: channel();
-            for _ in 0 .. cmp::min(jobs.as_usize(), times) {
-                let tx = tx.clone();
-                let sims_left = sims_left.clone();
-                thread::spawn(move|| {
-                    // acquire lock, fail if another task has failed, try to pop an item, and only continue if we got something
-                    while sims_left.lock().unwrap().pop().is_some() {
-                        tx.send(simulate(n)).unwrap();
+            self.qu.connected(self.virtual_top(), self.virtual_bottom())
+        }
+    }
+
+    /// Whether the system would percolate if `site` were open as well, without opening it. A torus only percolates
+    /// by wrapping around, which can't be told without opening the site, so this isn't available on one.
+    pub fn would_percolate(&self, site: usize) -> bool {
+        assert!(self.lattice.boundary != Boundary::Torus, "Percolation can't be looked ahead on a torus");
+        if self.percolates() || self.is_open_site(site) {
+            return self.percolates();
+        }
+        let touches = |node: usize| self.lattice.neighbours(site).iter()
+            .any(|&neighbour| self.grid.get(neighbour) && self.qu.connected(neighbour, node));
+        (self.lattice.is_top(site) || touches(self.virtual_top())) &&
+            (self.lattice.is_bottom(site) || touches(self.virtual_bottom()))
+    }
+
+    /// Returns which directions some cluster wraps around the lattice in; nothing wraps around a fixed boundary.
+    pub fn wrapping(&self) -> Wrapping {
+        self.wrapping.as_ref().map(|wrapping| wrapping.wrapping()).unwrap_or(Wrapping::default())
+    }
+
+    /// Returns a shortest chain of open sites from the top row to the bottom row, starting in the top row, or None if
+    /// the system doesn't percolate. A torus has no top or bottom row, so there is never a path across one.
+    pub fn spanning_path(&self) -> Option<Vec<usize>> {
+        if self.lattice.boundary == Boundary::Torus || !self.percolates() {
+            return None;
+        }
+        // breadth first search from every open site in the top row at once, remembering where each site was reached
+        // from so that the path can be traced back
+        let sites = self.lattice.site_count();
+        let mut reached_from: Vec<Option<usize>> = iter::repeat(None).take(sites).collect();
+        let mut queue = VecDeque::new();
+        for site in (0 .. sites).filter(|&site| self.lattice.is_top(site) && self.grid.get(site)) {
+            reached_from[site] = Some(site);
+            queue.push_back(site);
+        }
+        while let Some(site) = queue.pop_front() {
+            if self.lattice.is_bottom(site) {
+                let mut path = vec![site];
+                let mut current = site;
+                while let Some(previous) = reached_from[current] {
+                    if previous == current {
+                        break;
                     }
-                });
+                    path.push(previous);
+                    current = previous;
+                }
+                path.reverse();
+                return Some(path);
             }
-            (0..times).map(|_| rx.recv().unwrap()).collect()
-        },
+            for neighbour in self.lattice.neighbours(site) {
+                if self.grid.get(neighbour) && reached_from[neighbour].is_none() {
+                    reached_from[neighbour] = Some(site);
+                    queue.push_back(neighbour);
+                }
+            }
+        }
+        unreachable!("A percolating system has a path from the top row to the bottom row")
+    }
+
+    /// Returns every site in a cluster which connects the top row to the bottom row, in increasing order. This is
+    /// empty unless the system percolates, and like `spanning_path` it is always empty on a torus.
+    pub fn spanning_cluster(&self) -> Vec<usize> {
+        let sites = self.lattice.site_count();
+        let mut spanning_roots: Vec<bool> = iter::repeat(false).take(sites).collect();
+        for site in (0 .. sites).filter(|&site| self.lattice.is_bottom(site) && self.is_full_site(site)) {
+            spanning_roots[self.clusters.find(site)] = true;
+        }
+        (0 .. sites).filter(|&site| self.grid.get(site) && spanning_roots[self.clusters.find(site)])
+            .collect()
+    }
+}
+
+impl System for Percolation {
+    fn element_count(&self) -> usize {
+        self.lattice.site_count()
+    }
+
+    fn open_element(&mut self, element: usize) {
+        self.open_site(element)
+    }
+
+    fn number_of_open_elements(&self) -> usize {
+        self.number_of_open_sites()
+    }
+
+    fn cluster_size(&self, element: usize) -> usize {
+        self.cluster_size_of_site(element)
+    }
+
+    fn percolates(&self) -> bool {
+        Percolation::percolates(self)
+    }
+
+    fn would_percolate(&self, element: usize) -> bool {
+        Percolation::would_percolate(self, element)
+    }
+}
+
+/// A percolation system whose elements (sites or bonds, depending on the model) can be opened one at a time.
+pub trait System {
+    fn element_count(&self) -> usize;
+    fn open_element(&mut self, element: usize);
+    fn number_of_open_elements(&self) -> usize;
+    fn percolates(&self) -> bool;
+
+    /// Whether the system would percolate if `element` were open as well, without opening it.
+    fn would_percolate(&self, element: usize) -> bool;
+
+    /// Returns the number of sites in the cluster which `element` is part of.
+    fn cluster_size(&self, element: usize) -> usize;
+}
+
+/// Lets a system created by `Model::create` run wherever a concrete system can.
+impl System for Box<System> {
+    fn element_count(&self) -> usize {
+        (**self).element_count()
+    }
+
+    fn open_element(&mut self, element: usize) {
+        (**self).open_element(element)
+    }
+
+    fn number_of_open_elements(&self) -> usize {
+        (**self).number_of_open_elements()
+    }
+
+    fn percolates(&self) -> bool {
+        (**self).percolates()
+    }
+
+    fn would_percolate(&self, element: usize) -> bool {
+        (**self).would_percolate(element)
+    }
+
+    fn cluster_size(&self, element: usize) -> usize {
+        (**self).cluster_size(element)
+    }
+}
+
+/// Which elements of the lattice open at random, and how they connect.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Model {
+    Site,
+    Bond,
+    /// Sites which only connect downward, to the open sites below them.
+    Directed(Orientation),
+}
+
+impl Model {
+    /// Creates a system of the model on `lattice`, which for directed percolation mustn't be a torus.
+    pub fn create(&self, lattice: Lattice, uf: UnionFindKind) -> Box<System> {
+        match *self {
+            Model::Site => Box::new(Percolation::with_lattice(lattice, uf)),
+            Model::Bond => Box::new(BondPercolation::with_union_find(lattice, uf)),
+            Model::Directed(orientation) => Box::new(DirectedPercolation::with_union_find(lattice, orientation, uf)),
+        }
+    }
+
+    /// Returns the number of elements which can open on `lattice`.
+    pub fn element_count(&self, lattice: &Lattice) -> usize {
+        match *self {
+            Model::Site | Model::Directed(_) => lattice.site_count(),
+            Model::Bond => lattice.bonds().len(),
+        }
+    }
+
+    /// Whether the elements which open are the sites of the lattice.
+    pub fn opens_sites(&self) -> bool {
+        *self != Model::Bond
+    }
+}
+
+impl fmt::Display for Model {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", match *self {
+            Model::Site => "site",
+            Model::Bond => "bond",
+            Model::Directed(Orientation::Square) => "directed",
+            Model::Directed(Orientation::Tilted) => "directed-tilted",
+        })
+    }
+}
+
+impl FromStr for Model {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Model, String> {
+        match s {
+            "site" => Ok(Model::Site),
+            "bond" => Ok(Model::Bond),
+            "directed" => Ok(Model::Directed(Orientation::Square)),
+            "directed-tilted" => Ok(Model::Directed(Orientation::Tilted)),
+            _ => Err(format!("Unknown model '{}' (expected one of: site, bond, directed, directed-tilted)", s)),
+        }
+    }
+}
+
+/// Everything about a simulated trial except for its randomness.
+#[derive(Clone, Debug)]
+pub struct Simulation {
+    pub lattice: Lattice,
+    pub model: Model,
+    pub union_find: UnionFindKind,
+    pub strategy: Strategy,
+}
+
+impl Simulation {
+    pub fn new(n: usize) -> Simulation {
+        Simulation::on(Lattice::square(n))
+    }
+
+    pub fn on(lattice: Lattice) -> Simulation {
+        Simulation {
+            lattice: lattice,
+            model: Model::Site,
+            union_find: UnionFindKind::WeightedQuickUnion,
+            strategy: Strategy::Uniform,
+        }
+    }
+
+    /// Returns an opener for the elements of `system`, which has to be one of this simulation's systems.
+    pub fn opener<R: Rng>(&self, system: &System, rng: &mut R) -> Box<Opener> {
+        self.strategy.opener(&self.lattice, system.element_count(), rng)
+    }
+}
+
+/// Returns the random number generator for the `trial`th trial of a run with the given `seed`, so that the outcome
+/// of a trial does not depend on which thread runs it or when.
+pub fn trial_rng(seed: usize, trial: usize) -> StdRng {
+    SeedableRng::from_seed(&[seed, trial][..])
+}
+
+/// Opens elements of the system in the order of its strategy until it percolates, and returns the fraction that were
+/// opened.
+pub fn simulate<R: Rng>(sim: &Simulation, rng: &mut R) -> f32 {
+    let mut system = sim.model.create(sim.lattice, sim.union_find);
+    let mut opener = sim.opener(&*system, rng);
+    percolate_with(&mut system, &mut *opener)
+}
+
+/// Opens elements of any system in random order until it percolates, and returns the fraction that were opened.
+pub fn percolate<S: System, R: Rng>(system: &mut S, rng: &mut R) -> f32 {
+    let mut opener = strategy::uniform(system.element_count(), rng);
+    percolate_with(system, &mut *opener)
+}
+
+/// Opens the elements `opener` hands out until the system percolates, and returns the fraction that were opened.
+pub fn percolate_with<S: System>(system: &mut S, opener: &mut Opener) -> f32 {
+    percolate_with_steps(system, opener, |_| Ok::<(), ()>(())).unwrap() // safe: the steps never fail
+}
+
+/// Like `percolate_with`, but passes the system to `step` after each element opens, stopping at the first error.
+pub fn percolate_with_steps<S, F, E>(system: &mut S, opener: &mut Opener, mut step: F) -> Result<f32, E>
+        where S: System, F: FnMut(&S) -> Result<(), E> {
+    while !system.percolates() {
+        // safe: system must percolate before we run out of elements to open
+        let element = opener.next(system).unwrap();
+        system.open_element(element);
+        try!(step(system));
     }
+    Ok(system.number_of_open_elements() as f32 / system.element_count() as f32)
+}
+
+/// Runs `times` trials of `sim` spread over at most `jobs` threads. The results are in trial order and only depend
+/// on `seed`, not on the number of jobs. If Ctrl-C interrupts the trials, only the ones which finished are included.
+pub fn simulate_multiple(sim: &Simulation, times: usize, jobs: u32, seed: usize) -> PercolationStats {
+    let mut results: Vec<Option<f32>> = iter::repeat(None).take(times).collect();
+    simulate_trials_while(sim, (0 .. times).collect(), jobs, seed, |trial, result| {
+        results[trial] = Some(result);
+        true
+    });
+    PercolationStats::new(results.into_iter().filter_map(|result| result).collect())
 }
 
-#[derive(Debug)]
-pub struct PercolationStats {
-    results: Vec<f32>,
+/// Runs trials of `sim` until the 95% confidence interval of the mean threshold is narrower than ±`precision`, or
+/// until `max_times` trials have run. Trials run concurrently over at most `jobs` threads, but the interval is only
+/// ever checked over the first trials by trial number, so as with `simulate_multiple` the number of trials used and
+/// their results only depend on `seed`.
+pub fn simulate_until(sim: &Simulation, precision: f32, max_times: usize, jobs: u32, seed: usize) -> PercolationStats {
+    let mut results: Vec<Option<f32>> = iter::repeat(None).take(max_times).collect();
+    // statistics of the trials before the first one which hasn't finished yet
+    let mut finished = RunningStats::default();
+    simulate_trials_while(sim, (0 .. max_times).collect(), jobs, seed, |trial, result| {
+        results[trial] = Some(result);
+        while finished.count() < max_times {
+            match results[finished.count()] {
+                Some(result) => finished.push(result),
+                None => break,
+            }
+            if finished.count() >= MIN_ADAPTIVE_TRIALS && finished.confidence_half_width() < precision {
+                return false;
+            }
+        }
+        true
+    });
+    PercolationStats::new(results.into_iter().take(finished.count()).map(|result| result.unwrap()).collect())
+}
+
+/// Runs each of `trials` of `sim` spread over at most `jobs` threads, and hands its trial number and threshold to
+/// `collect` in whatever order they finish, until `collect` returns false. A trial's random numbers only depend on
+/// `seed` and its number, so it has the same threshold whichever other trials run with it.
+pub fn simulate_trials_while<C>(sim: &Simulation, trials: Vec<usize>, jobs: u32, seed: usize, mut collect: C)
+        where C: FnMut(usize, f32) -> bool {
+    let sim = sim.clone();
+    run_trials_while(trials.len(), jobs, move |i| {
+        let trial = trials[i];
+        (trial, simulate(&sim, &mut trial_rng(seed, trial)))
+    }, |_, (trial, result)| collect(trial, result))
+}
+
+/// Fewest trials `simulate_until` runs, so that it doesn't stop on a confidence interval which is narrow by chance.
+const MIN_ADAPTIVE_TRIALS: usize = 10;
+
+/// Runs `run(trial)` for each of `times` trials spread over at most `jobs` threads, and hands each result to
+/// `collect` on the calling thread in whatever order the trials finish.
+pub fn run_trials<T, R, C>(times: usize, jobs: u32, run: R, mut collect: C)
+        where T: Send + 'static, R: Fn(usize) -> T + Send + Sync + 'static, C: FnMut(usize, T) {
+    run_trials_while(times, jobs, run, |trial, result| {
+        collect(trial, result);
+        true
+    })
 }
 
-impl PercolationStats {
-    pub fn mean(&self) -> f32 {
-        let mut mean = 0f32;
-        for (i, &r) in self.results.iter().enumerate() {
-            mean = (mean * i as f32 + r ) / (i + 1) as f32;
+/// Most trials handed to a worker at once. Chunks save workers from contending for every trial, but they shrink with
+/// the trials left to hand out, so that no worker is left running a long chunk while the others sit idle.
+const MAX_CHUNK: usize = 64;
+
+/// Hands out the next chunk of `times` trials as its first trial and size, or None once all of them are handed out.
+/// Each chunk is a share of the trials left, so that the chunks get smaller towards the end.
+fn next_chunk(next: &AtomicUsize, times: usize, jobs: usize) -> Option<(usize, usize)> {
+    loop {
+        let first = next.load(Ordering::SeqCst);
+        if first >= times {
+            return None;
+        }
+        // aim for several chunks per worker, so that workers which finish early can take on more of the trials
+        let chunk = cmp::max(1, cmp::min(MAX_CHUNK, (times - first) / (jobs * 2)));
+        if next.compare_and_swap(first, first + chunk, Ordering::SeqCst) == first {
+            return Some((first, chunk));
         }
-        mean
     }
 }
 
+/// Like `run_trials`, but stops starting new trials as soon as `collect` returns false. Trials which are already
+/// running when that happens are discarded. New trials also stop starting once Ctrl-C is pressed (see
+/// `progress::handle_interrupts`), so that `collect` only ever sees some of the trials.
+pub fn run_trials_while<T, R, C>(times: usize, jobs: u32, run: R, mut collect: C)
+        where T: Send + 'static, R: Fn(usize) -> T + Send + Sync + 'static, C: FnMut(usize, T) -> bool {
+    let _interrupts = progress::handle_interrupts();
+    let mut progress = Progress::new(times);
+    if jobs == 1 {
+        for trial in 0..times {
+            if progress::interrupted() || !collect(trial, run(trial)) {
+                break;
+            }
+            progress.advance(1);
+        }
+    } else {
+        use std::thread;
+        use std::sync::Arc;
+        use std::sync::atomic::AtomicBool;
+        use std::sync::mpsc;
+
+        let jobs = cmp::min(jobs.as_usize(), times);
+        // first trial of the next chunk to hand out
+        let next = Arc::new(AtomicUsize::new(0));
+        // set once the results are no longer wanted
+        let stop = Arc::new(AtomicBool::new(false));
+        let run = Arc::new(run);
+
+        let (tx, rx) = mpsc::channel();
+        for _ in 0 .. jobs {
+            let (tx, next, stop, run) = (tx.clone(), next.clone(), stop.clone(), run.clone());
+            thread::spawn(move|| {
+                let stopped = || stop.load(Ordering::SeqCst) || progress::interrupted();
+                while !stopped() {
+                    let (first, chunk) = match next_chunk(&next, times, jobs) {
+                        Some(chunk) => chunk,
+                        None => break,
+                    };
+                    let mut results = Vec::with_capacity(chunk);
+                    for trial in first .. cmp::min(first + chunk, times) {
+                        results.push((trial, run(trial)));
+                        if stopped() {
+                            break;
+                        }
+                    }
+                    // the receiver is gone once the results are no longer wanted
+                    if tx.send(results).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        // every worker holds its own sender, so the results run out once all of them have stopped
+        drop(tx);
+
+        for results in rx.iter() {
+            for (trial, result) in results.into_iter() {
+                if stop.load(Ordering::SeqCst) {
+                    break;
+                }
+                if collect(trial, result) {
+                    progress.advance(1);
+                } else {
+                    stop.store(true, Ordering::SeqCst);
+                }
+            }
+        }
+    }
+    progress.finish();
+}
+
 #[cfg(test)]
 mod tests {
-    use super::Percolation;
+    use std::usize;
+    use std::sync::atomic::AtomicUsize;
+    use super::{MemoryUsage, Model, Percolation, PercolationError, Simulation, check_lattice, next_chunk, run_trials,
+        run_trials_while, simulate_multiple, simulate_until};
+    use lattice::{Boundary, Lattice};
+    use unionfind::UnionFindKind;
 
     #[test]
     fn percolation_all_open_at_start() {
@@ -160,12 +760,312 @@ mod tests {
         }
     }
 
+    #[test]
+    fn percolation_works_with_every_union_find() {
+        for uf in UnionFindKind::all() {
+            let mut perc = Percolation::with_union_find(3, uf);
+            perc.open(1, 1);
+            perc.open(2, 1);
+            assert!(!perc.percolates());
+            perc.open(3, 1);
+            assert!(perc.percolates(), "{} should percolate through an open column", uf);
+        }
+    }
+
+    #[test]
+    fn percolation_addresses_sites_by_row_then_column() {
+        let mut perc = Percolation::new(3);
+        // the whole top row, as in the spec, rather than the first column
+        perc.open(1, 1);
+        perc.open(1, 2);
+        perc.open(1, 3);
+        assert!(!perc.percolates());
+        assert!(perc.is_full(1, 3));
+        assert!(!perc.is_open(3, 1));
+    }
+
+    #[test]
+    fn percolation_rows_run_from_top_to_bottom() {
+        let mut perc = Percolation::new(3);
+        // the first row index is the top row, and the second index moves along it
+        perc.open(1, 2);
+        perc.open(2, 2);
+        perc.open(3, 2);
+        assert!(perc.percolates());
+        assert!(perc.is_full(3, 2));
+        assert!(perc.is_open(2, 2) && !perc.is_open(2, 1) && !perc.is_open(2, 3));
+        assert_eq!(perc.cluster_size_of_site(4), 3);
+    }
+
+    #[test]
+    fn percolation_of_single_site_requires_it_to_be_open() {
+        let mut perc = Percolation::new(1);
+        assert!(!perc.percolates());
+        assert!(!perc.is_full(1, 1));
+
+        perc.open(1, 1);
+        assert!(perc.percolates());
+        assert!(perc.is_full(1, 1));
+    }
+
+    #[test]
+    fn percolation_counts_each_open_site_once() {
+        let mut perc = Percolation::new(4);
+        assert_eq!(perc.number_of_open_sites(), 0);
+
+        perc.open(2, 3);
+        perc.open(2, 3);
+        perc.open(4, 4);
+        assert_eq!(perc.number_of_open_sites(), 2);
+    }
+
+    #[test]
+    fn percolation_is_full_only_when_connected_to_top() {
+        let mut perc = Percolation::new(3);
+        perc.open(2, 2);
+        assert!(!perc.is_full(2, 2));
+
+        perc.open(1, 2);
+        assert!(perc.is_full(1, 2));
+        assert!(perc.is_full(2, 2));
+        assert!(!perc.is_full(1, 1));
+    }
+
+    #[test]
+    fn percolation_does_not_backwash_into_bottom_row() {
+        let mut perc = Percolation::new(3);
+        perc.open(1, 1);
+        perc.open(2, 1);
+        perc.open(3, 1);
+        perc.open(3, 3);
+        assert!(perc.percolates());
+
+        assert!(perc.is_full(3, 1));
+        assert!(perc.is_open(3, 3));
+        assert!(!perc.is_full(3, 3));
+    }
+
+    #[test]
+    fn simulations_with_same_seed_match_regardless_of_jobs() {
+        let sim = Simulation::new(20);
+
+        let sequential = simulate_multiple(&sim, 12, 1, 42);
+        let parallel = simulate_multiple(&sim, 12, 4, 42);
+        assert_eq!(sequential.results(), parallel.results());
+        assert!(simulate_multiple(&sim, 12, 4, 43).results() != sequential.results());
+    }
+
+    #[test]
+    fn trials_are_handed_out_in_chunks_and_each_run_once() {
+        for &(times, jobs) in [(1000, 3), (5, 8), (0, 2)].iter() {
+            let mut seen: Vec<usize> = Vec::new();
+            run_trials(times, jobs, |trial| trial * 2, |trial, result| {
+                assert_eq!(result, trial * 2);
+                seen.push(trial);
+            });
+            seen.sort();
+            assert_eq!(seen, (0 .. times).collect::<Vec<usize>>());
+        }
+
+        let mut collected = 0;
+        run_trials_while(1000, 4, |trial| trial, |_, _| {
+            collected += 1;
+            collected < 10
+        });
+        assert_eq!(collected, 10);
+    }
+
+    #[test]
+    fn chunks_shrink_towards_the_last_trials() {
+        let next = AtomicUsize::new(0);
+        let mut chunks = Vec::new();
+        while let Some((first, chunk)) = next_chunk(&next, 1000, 4) {
+            assert_eq!(first, chunks.iter().fold(0, |sum, &chunk| sum + chunk));
+            chunks.push(chunk);
+        }
+        assert_eq!(chunks.iter().fold(0, |sum, &chunk| sum + chunk), 1000);
+        assert_eq!((chunks[0], chunks[chunks.len() - 1]), (64, 1));
+        assert!(chunks.windows(2).all(|pair| pair[0] >= pair[1]));
+    }
+
+    #[test]
+    fn adaptive_simulations_stop_once_precise_enough() {
+        let sim = Simulation::new(20);
+
+        let sequential = simulate_until(&sim, 0.02, 500, 1, 42);
+        let parallel = simulate_until(&sim, 0.02, 500, 4, 42);
+        assert!(sequential.trials() >= 10 && sequential.trials() < 500);
+        assert!(sequential.confidence_half_width() < 0.02);
+        assert_eq!(sequential.results(), parallel.results());
+        // the trials are the same ones a fixed number of trials would run
+        assert_eq!(sequential.results(), simulate_multiple(&sim, sequential.trials(), 1, 42).results());
+
+        assert_eq!(simulate_until(&sim, 0.0, 15, 4, 42).trials(), 15);
+    }
+
+    #[test]
+    fn spanning_path_is_a_shortest_chain_of_open_sites() {
+        let mut perc = Percolation::new(4);
+        // straight down the left column, and a longer way round down the third column which joins it at the bottom
+        for &(i, j) in [(1, 1), (2, 1), (3, 1), (4, 1), (4, 2), (1, 3), (2, 3), (3, 3), (3, 2), (1, 4)].iter() {
+            perc.open(i, j);
+        }
+        assert_eq!(perc.spanning_path(), Some(vec![0, 4, 8, 12]));
+        assert_eq!(perc.spanning_cluster(), vec![0, 2, 3, 4, 6, 8, 9, 10, 12, 13]);
+
+        perc.open(4, 3);
+        assert_eq!(perc.spanning_path().map(|path| path.len()), Some(4));
+    }
+
+    #[test]
+    fn nothing_spans_until_the_system_percolates() {
+        let mut perc = Percolation::new(3);
+        perc.open(1, 1);
+        perc.open(2, 1);
+        perc.open(3, 3);
+        assert_eq!(perc.spanning_path(), None);
+        assert_eq!(perc.spanning_cluster(), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn rectangular_percolation_spans_its_rows() {
+        let mut perc = Percolation::with_lattice(Lattice::rectangle(2, 5), UnionFindKind::WeightedQuickUnion);
+        perc.open(1, 5);
+        assert!(!perc.percolates());
+        perc.open(2, 5);
+        assert!(perc.percolates());
+        assert!(perc.is_full(2, 5));
+    }
+
+    #[test]
+    fn cubic_percolation_spans_from_top_face_to_bottom_face() {
+        let cube = Lattice::cube(3);
+        let mut perc = Percolation::with_lattice(cube, UnionFindKind::WeightedQuickUnion);
+        // a path down the back layer which has to step between layers half way
+        perc.open_site(cube.index(0, 1, 2));
+        perc.open_site(cube.index(1, 1, 2));
+        perc.open_site(cube.index(1, 1, 1));
+        assert!(!perc.percolates());
+        perc.open_site(cube.index(2, 1, 1));
+        assert!(perc.percolates());
+        assert!(perc.is_full_site(cube.index(1, 1, 1)));
+    }
+
+    #[test]
+    fn simulations_run_on_every_lattice() {
+        for lattice in vec![Lattice::square(8), Lattice::rectangle(4, 16), Lattice::cube(5)] {
+            let stats = simulate_multiple(&Simulation::on(lattice), 3, 1, 1);
+            assert!(stats.min() > 0.0 && stats.max() <= 1.0);
+        }
+    }
+
+    #[test]
+    fn cylinder_clusters_connect_across_the_side_edges() {
+        let mut perc = Percolation::with_lattice(Lattice::square(3).with_boundary(Boundary::Cylinder),
+                UnionFindKind::WeightedQuickUnion);
+        perc.open(1, 1);
+        perc.open(2, 1);
+        perc.open(2, 3);
+        perc.open(3, 3);
+        assert!(perc.percolates());
+        assert!(!perc.wrapping().any());
+
+        perc.open(2, 2);
+        assert!(perc.wrapping().horizontally);
+        assert!(!perc.wrapping().vertically);
+    }
+
+    #[test]
+    fn torus_percolates_once_a_cluster_wraps_vertically() {
+        let mut perc = Percolation::with_lattice(Lattice::square(3).with_boundary(Boundary::Torus),
+                UnionFindKind::WeightedQuickUnion);
+        perc.open(1, 2);
+        perc.open(2, 2);
+        assert!(!perc.percolates());
+
+        perc.open(3, 2);
+        assert!(perc.percolates());
+        assert!(!perc.wrapping().horizontally);
+        assert!(!perc.is_full(1, 2), "a torus has no top row for sites to be connected to");
+    }
+
+    #[test]
+    fn cluster_sizes_only_count_connected_open_sites() {
+        let mut perc = Percolation::new(3);
+        assert_eq!(perc.cluster_size_of_site(0), 0);
+
+        perc.open(1, 1);
+        perc.open(1, 3);
+        assert_eq!(perc.cluster_size_of_site(0), 1);
+
+        perc.open(2, 3);
+        perc.open(3, 3);
+        perc.open(3, 1);
+        assert_eq!(perc.cluster_size_of_site(2), 3);
+        assert_eq!(perc.cluster_size_of_site(6), 1);
+    }
+
+    #[test]
+    fn bond_simulations_open_a_fraction_of_the_bonds() {
+        let sim = Simulation { model: Model::Bond, ..Simulation::new(10) };
+        let stats = simulate_multiple(&sim, 4, 2, 3);
+        assert!(stats.min() > 0.0 && stats.max() <= 1.0);
+    }
+
     #[test]
     #[should_panic(expected = "Out of bounds: (9, 0)")]
     fn percolation_is_open_for_out_of_bounds_should_panic() {
         Percolation::new(10usize).is_open(9, 0);
     }
 
+    #[test]
+    fn fallible_api_reports_errors_instead_of_panicking() {
+        let mut perc = Percolation::try_new(3).unwrap();
+        assert_eq!(perc.try_open(1, 2), Ok(()));
+        assert_eq!(perc.try_is_open(1, 2), Ok(true));
+        assert_eq!(perc.try_is_full(1, 2), Ok(true));
+        assert_eq!(perc.try_open(4, 1), Err(PercolationError::OutOfBounds { i: 4, j: 1, lattice: Lattice::square(3) }));
+        assert_eq!(perc.try_is_open(0, 1).unwrap_err().to_string(), "Out of bounds: (0, 1) on a 3x3 lattice");
+        assert_eq!(perc.number_of_open_sites(), 1);
+
+        assert_eq!(Percolation::try_new(0).err(), Some(PercolationError::Empty));
+        // grids past 65536 x 65536 number their sites with u64, so they're only limited by a usize
+        assert_eq!(check_lattice(&Lattice::square((1 << 16) + 1)), Ok(()));
+        assert_eq!(Percolation::try_new(usize::MAX).err(), Some(PercolationError::TooLarge(Lattice::square(usize::MAX))));
+        assert!(check_lattice(&Lattice::cube(1 << 30)).is_err());
+
+        // a single row of a torus never wraps around vertically, so trials on it would run out of sites to open
+        let ring = Lattice::rectangle(1, 4).with_boundary(Boundary::Torus);
+        assert_eq!(check_lattice(&ring), Err(PercolationError::NeverPercolates(ring)));
+        assert_eq!(check_lattice(&Lattice::rectangle(2, 1).with_boundary(Boundary::Torus)), Ok(()));
+        assert_eq!(check_lattice(&Lattice::rectangle(1, 4)), Ok(()));
+    }
+
+    #[test]
+    fn memory_usage_is_reported_for_each_part() {
+        let usage = Percolation::new(100).memory_usage();
+        // 10000 sites need 157 words of bits, and the weighted union-finds a u32 id and size for each node
+        assert_eq!(usage.grid, 157 * 8);
+        assert_eq!(usage.full_roots, 157 * 8);
+        assert_eq!(usage.qu, 2 * 10002 * 4);
+        assert_eq!(usage.clusters, 2 * 10000 * 4);
+        assert_eq!(usage.wrapping, 0);
+        assert_eq!(usage.total(), 2 * 157 * 8 + 2 * 20002 * 4);
+
+        let torus = Percolation::with_lattice(Lattice::square(100).with_boundary(Boundary::Torus),
+            UnionFindKind::QuickFind).memory_usage();
+        assert_eq!(torus.qu, 10002 * 4);
+        assert!(torus.wrapping > 0);
+
+        // the figures can be worked out without building the system
+        for &boundary in [Boundary::Fixed, Boundary::Cylinder, Boundary::Torus].iter() {
+            for uf in UnionFindKind::all() {
+                let lattice = Lattice::rectangle(30, 70).with_boundary(boundary);
+                assert_eq!(MemoryUsage::of(&lattice, uf), Percolation::with_lattice(lattice, uf).memory_usage());
+            }
+        }
+    }
+
     #[test]
     #[should_panic(expected = "Out of bounds: (0, 5)")]
     fn percolation_opening_out_of_bounds_should_panic() {
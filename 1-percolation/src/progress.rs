@@ -0,0 +1,146 @@
+use std::io::{self, Write};
+use std::sync::atomic::{ATOMIC_BOOL_INIT, AtomicBool, Ordering};
+#[cfg(unix)]
+use libc::{self, c_int, sighandler_t};
+use time;
+
+static REPORTING: AtomicBool = ATOMIC_BOOL_INIT;
+static INTERRUPTED: AtomicBool = ATOMIC_BOOL_INIT;
+
+/// Fewest seconds between progress reports, so that quick trials don't flood stderr.
+const REPORT_INTERVAL_S: f64 = 0.5;
+
+/// Turns on progress reports on stderr for every batch of trials run from now on.
+pub fn report_progress() {
+    REPORTING.store(true, Ordering::SeqCst);
+}
+
+/// Whether Ctrl-C has been pressed since the latest batch of trials started (see `handle_interrupts`).
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// The default action for a signal, which POSIX defines as 0.
+#[cfg(unix)]
+const SIG_DFL: sighandler_t = 0;
+
+#[cfg(unix)]
+extern fn on_interrupt(_: c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+    // restore the default handler, so that a second Ctrl-C kills the process if stopping takes too long
+    unsafe { libc::signal(libc::SIGINT, SIG_DFL); }
+}
+
+/// Catches the first Ctrl-C rather than letting it kill the process until the returned guard is dropped: trials stop
+/// starting once it's pressed, and the ones which have finished are still reported. Outside of trials there is
+/// nothing to stop early, so Ctrl-C kills the process as usual.
+pub fn handle_interrupts() -> InterruptHandler {
+    // a Ctrl-C during an earlier batch of trials was already dealt with by stopping that batch
+    INTERRUPTED.store(false, Ordering::SeqCst);
+    install(true);
+    InterruptHandler
+}
+
+/// Restores the default handling of Ctrl-C when dropped.
+pub struct InterruptHandler;
+
+impl Drop for InterruptHandler {
+    fn drop(&mut self) {
+        install(false);
+    }
+}
+
+#[cfg(unix)]
+fn install(catch: bool) {
+    let handler = if catch { on_interrupt as extern fn(c_int) as sighandler_t } else { SIG_DFL };
+    unsafe { libc::signal(libc::SIGINT, handler); }
+}
+
+#[cfg(not(unix))]
+fn install(_: bool) {}
+
+/// Tracks how many of a batch of trials are done, and reports it with the estimated time left on a single line of
+/// stderr if progress reports are turned on.
+pub struct Progress {
+    total: usize,
+    done: usize,
+    start: f64,
+    last_report: f64,
+    reporting: bool,
+}
+
+impl Progress {
+    pub fn new(total: usize) -> Progress {
+        let now = time::precise_time_s();
+        Progress { total: total, done: 0, start: now, last_report: now, reporting: REPORTING.load(Ordering::SeqCst) }
+    }
+
+    pub fn advance(&mut self, trials: usize) {
+        self.done += trials;
+        let now = time::precise_time_s();
+        if self.reporting && now - self.last_report >= REPORT_INTERVAL_S {
+            self.last_report = now;
+            self.report(now, false);
+        }
+    }
+
+    /// Reports the final count, ending the line of reports.
+    pub fn finish(&mut self) {
+        if self.reporting {
+            self.report(time::precise_time_s(), true);
+        }
+    }
+
+    fn report(&self, now: f64, last: bool) {
+        let elapsed = now - self.start;
+        let eta = match estimate_remaining(self.done, self.total, elapsed) {
+            Some(remaining) => format_duration(remaining),
+            None => "unknown".to_string(),
+        };
+        let mut stderr = io::stderr();
+        // progress is only a courtesy, so failing to write it isn't worth stopping for
+        let _ = write!(stderr, "\r{}/{} trials, {} elapsed, ETA {}{}", self.done, self.total, format_duration(elapsed),
+            eta, if last { "\n" } else { "   " });
+    }
+}
+
+/// Estimates the seconds left to finish `total` trials from the time it took to finish the first `done`.
+pub fn estimate_remaining(done: usize, total: usize, elapsed_s: f64) -> Option<f64> {
+    if done == 0 {
+        None
+    } else {
+        Some(elapsed_s / done as f64 * (total - done) as f64)
+    }
+}
+
+/// Formats a number of seconds as hours, minutes and seconds, leaving out the larger units while they're 0.
+pub fn format_duration(seconds: f64) -> String {
+    let seconds = seconds.round() as u64;
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{}h{:02}m{:02}s", hours, minutes, seconds)
+    } else if minutes > 0 {
+        format!("{}m{:02}s", minutes, seconds)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{estimate_remaining, format_duration};
+
+    #[test]
+    fn remaining_time_is_extrapolated_from_the_trials_done() {
+        assert_eq!(estimate_remaining(0, 10, 3.0), None);
+        assert_eq!(estimate_remaining(4, 10, 2.0), Some(3.0));
+        assert_eq!(estimate_remaining(10, 10, 5.0), Some(0.0));
+    }
+
+    #[test]
+    fn durations_leave_out_empty_larger_units() {
+        assert_eq!(format_duration(4.4), "4s");
+        assert_eq!(format_duration(65.0), "1m05s");
+        assert_eq!(format_duration(3600.0 + 59.6), "1h01m00s");
+    }
+}
@@ -0,0 +1,289 @@
+use std::io::{self, Write};
+use std::iter;
+use std::str::FromStr;
+use bitset::BitSet;
+use percolation::{self, Percolation, Simulation};
+use rand::Rng;
+
+pub type Colour = [u8; 3];
+
+pub const BLOCKED: Colour = [0, 0, 0];
+pub const OPEN: Colour = [255, 255, 255];
+pub const FULL: Colour = [107, 174, 214];
+pub const PATH: Colour = [214, 39, 40];
+// fills the gaps between the layers of a 3D lattice
+pub const BACKGROUND: Colour = [128, 128, 128];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    Ppm,
+    Png,
+}
+
+impl ImageFormat {
+    pub fn extension(&self) -> &'static str {
+        match *self {
+            ImageFormat::Ppm => "ppm",
+            ImageFormat::Png => "png",
+        }
+    }
+}
+
+impl FromStr for ImageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ImageFormat, String> {
+        match s {
+            "ppm" => Ok(ImageFormat::Ppm),
+            "png" => Ok(ImageFormat::Png),
+            _ => Err(format!("Unknown image format '{}' (expected one of: ppm, png)", s)),
+        }
+    }
+}
+
+/// An RGB image, stored row by row from the top left.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Colour>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize, colour: Colour) -> Image {
+        Image { width: width, height: height, pixels: iter::repeat(colour).take(width * height).collect() }
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Colour {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn fill(&mut self, x: usize, y: usize, width: usize, height: usize, colour: Colour) {
+        for row in y .. y + height {
+            for col in x .. x + width {
+                self.pixels[row * self.width + col] = colour;
+            }
+        }
+    }
+
+    pub fn write<W: Write>(&self, out: &mut W, format: ImageFormat) -> io::Result<()> {
+        match format {
+            ImageFormat::Ppm => self.write_ppm(out),
+            ImageFormat::Png => self.write_png(out),
+        }
+    }
+
+    /// Writes the image as a binary PPM (P6).
+    pub fn write_ppm<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        try!(write!(out, "P6\n{} {}\n255\n", self.width, self.height));
+        let bytes = self.pixels.iter().flat_map(|colour| colour.iter().cloned()).collect::<Vec<u8>>();
+        out.write_all(&bytes)
+    }
+
+    /// Writes the image as a PNG. The image data is stored uncompressed, which keeps this free of any dependencies at
+    /// the cost of larger files.
+    pub fn write_png<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        try!(out.write_all(&[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n']));
+
+        let mut header = Vec::with_capacity(13);
+        header.push_all(&be_bytes(self.width as u32));
+        header.push_all(&be_bytes(self.height as u32));
+        // 8 bits per channel, RGB, default compression, filtering and no interlacing
+        header.push_all(&[8, 2, 0, 0, 0]);
+        try!(write_chunk(out, b"IHDR", &header));
+
+        // each scanline starts with its filter type, which is always none
+        let mut raw = Vec::with_capacity(self.height * (1 + 3 * self.width));
+        for row in self.pixels.chunks(self.width) {
+            raw.push(0);
+            for colour in row {
+                raw.push_all(colour);
+            }
+        }
+        try!(write_chunk(out, b"IDAT", &zlib_stored(&raw)));
+        write_chunk(out, b"IEND", &[])
+    }
+}
+
+/// Draws each site of `perc` as a `scale` x `scale` square. The layers of a 3D lattice are drawn side by side, from
+/// left to right. Once the system percolates, a shortest spanning path is drawn over the full sites.
+pub fn render(perc: &Percolation, scale: usize) -> Image {
+    let lattice = *perc.lattice();
+    // marking the path up front keeps drawing linear in the number of sites, and the search for a path is only run
+    // once there is one to find
+    let mut on_path = BitSet::new(lattice.site_count());
+    if perc.percolates() {
+        for site in perc.spanning_path().unwrap_or(Vec::new()) {
+            on_path.set(site, true);
+        }
+    }
+    let width = (lattice.cols * lattice.layers + lattice.layers - 1) * scale;
+    let mut image = Image::new(width, lattice.rows * scale, BACKGROUND);
+    for site in 0 .. lattice.site_count() {
+        let (row, col, layer) = lattice.coordinates(site);
+        let colour = if on_path.get(site) {
+            PATH
+        } else if perc.is_full_site(site) {
+            FULL
+        } else if perc.is_open_site(site) {
+            OPEN
+        } else {
+            BLOCKED
+        };
+        let x = (layer * (lattice.cols + 1) + col) * scale;
+        image.fill(x, row * scale, scale, scale, colour);
+    }
+    image
+}
+
+/// Runs a site percolation trial of `sim` just like `percolation::simulate`, additionally passing `frame` a
+/// rendering of the grid before anything opens, after every `every` sites open and once the system percolates.
+/// Frames are numbered from 0. Returns the percolation threshold.
+pub fn simulate_frames<R, F>(sim: &Simulation, rng: &mut R, every: usize, scale: usize, mut frame: F)
+        -> io::Result<f32> where R: Rng, F: FnMut(usize, &Image) -> io::Result<()> {
+    let mut perc = Percolation::with_lattice(sim.lattice, sim.union_find);
+    let mut opener = sim.opener(&perc, rng);
+
+    try!(frame(0, &render(&perc, scale)));
+    let mut frames = 1;
+    percolation::percolate_with_steps(&mut perc, &mut *opener, |perc| {
+        if perc.number_of_open_sites() % every == 0 || perc.percolates() {
+            try!(frame(frames, &render(perc, scale)));
+            frames += 1;
+        }
+        Ok(())
+    })
+}
+
+fn be_bytes(x: u32) -> [u8; 4] {
+    [(x >> 24) as u8, (x >> 16) as u8, (x >> 8) as u8, x as u8]
+}
+
+fn write_chunk<W: Write>(out: &mut W, kind: &[u8], data: &[u8]) -> io::Result<()> {
+    try!(out.write_all(&be_bytes(data.len() as u32)));
+    try!(out.write_all(kind));
+    try!(out.write_all(data));
+    let mut checked = kind.to_vec();
+    checked.push_all(data);
+    out.write_all(&be_bytes(crc32(&checked)))
+}
+
+/// Wraps `data` in a zlib stream made of uncompressed deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    const MAX_BLOCK: usize = 65535;
+    let mut stream = vec![0x78, 0x01];
+    let mut blocks = data.chunks(MAX_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        stream.push_all(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let len = block.len() as u16;
+        stream.push(if last { 1 } else { 0 });
+        stream.push_all(&[len as u8, (len >> 8) as u8, !len as u8, (!len >> 8) as u8]);
+        stream.push_all(block);
+    }
+    stream.push_all(&be_bytes(adler32(data)));
+    stream
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffffffffu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0 .. 8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb88320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BACKGROUND, BLOCKED, FULL, OPEN, PATH, Image, adler32, crc32, render, simulate_frames};
+    use lattice::Lattice;
+    use percolation::{Percolation, Simulation, simulate, trial_rng};
+    use unionfind::UnionFindKind;
+
+    #[test]
+    fn sites_are_coloured_by_state() {
+        let mut perc = Percolation::new(3);
+        perc.open(1, 1);
+        perc.open(3, 3);
+        let image = render(&perc, 2);
+
+        assert_eq!((image.width, image.height), (6, 6));
+        assert_eq!(image.pixel(1, 1), FULL);
+        assert_eq!(image.pixel(5, 4), OPEN);
+        assert_eq!(image.pixel(2, 0), BLOCKED);
+    }
+
+    #[test]
+    fn spanning_path_is_highlighted() {
+        let mut perc = Percolation::new(2);
+        perc.open(1, 1);
+        perc.open(1, 2);
+        perc.open(2, 2);
+        let image = render(&perc, 1);
+
+        assert_eq!(image.pixel(0, 0), FULL);
+        assert_eq!(image.pixel(1, 0), PATH);
+        assert_eq!(image.pixel(1, 1), PATH);
+    }
+
+    #[test]
+    fn layers_are_drawn_side_by_side() {
+        let mut perc = Percolation::with_lattice(Lattice::cube(2), UnionFindKind::WeightedQuickUnion);
+        perc.open_site(4);
+        let image = render(&perc, 1);
+
+        assert_eq!((image.width, image.height), (5, 2));
+        assert_eq!(image.pixel(2, 0), BACKGROUND);
+        assert_eq!(image.pixel(3, 0), FULL);
+    }
+
+    #[test]
+    fn ppm_has_a_header_and_three_bytes_per_pixel() {
+        let mut out = Vec::new();
+        Image::new(2, 1, OPEN).write_ppm(&mut out).unwrap();
+
+        assert_eq!(&out[..11], &b"P6\n2 1\n255\n"[..]);
+        assert_eq!(out.len(), 11 + 6);
+    }
+
+    #[test]
+    fn png_checksums_match_known_values() {
+        assert_eq!(crc32(b"IEND"), 0xae426082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+
+        let mut out = Vec::new();
+        Image::new(3, 2, FULL).write_png(&mut out).unwrap();
+        assert_eq!(&out[1..4], &b"PNG"[..]);
+        assert_eq!(out[out.len() - 12..].to_vec(), vec![0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xae, 0x42, 0x60, 0x82]);
+    }
+
+    #[test]
+    fn frames_follow_the_same_trial_as_simulate() {
+        let sim = Simulation::new(5);
+        let mut frames = 0;
+        let threshold = simulate_frames(&sim, &mut trial_rng(3, 0), 4, 1, |number, image| {
+            assert_eq!(number, frames);
+            assert_eq!((image.width, image.height), (5, 5));
+            frames += 1;
+            Ok(())
+        }).unwrap();
+
+        assert_eq!(threshold, simulate(&sim, &mut trial_rng(3, 0)));
+        let opened = (threshold * 25.0).round() as usize;
+        assert_eq!(frames, 1 + opened / 4 + if opened % 4 == 0 { 0 } else { 1 });
+    }
+}
@@ -0,0 +1,203 @@
+use std::str::FromStr;
+use lattice::Lattice;
+use percolation::{self, Percolation};
+use unionfind::UnionFindKind;
+
+/// An input file in the format of the Princeton percolation assignment: the size n of an n x n grid, followed by the
+/// (row, column) of each site to open, in order. Rows and columns count from 1 at the top left.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Input {
+    pub n: usize,
+    pub sites: Vec<(usize, usize)>,
+}
+
+impl FromStr for Input {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Input, String> {
+        let mut lines = s.lines().enumerate().filter(|&(_, line)| !line.trim().is_empty());
+        let n = match lines.next() {
+            Some((number, line)) => match line.trim().parse::<usize>() {
+                Ok(n) => {
+                    try!(percolation::check_lattice(&Lattice::square(n))
+                        .map_err(|e| format!("Line {}: {}", number + 1, e)));
+                    n
+                },
+                _ => return Err(format!("Line {}: expected the grid size, found '{}'", number + 1, line)),
+            },
+            None => return Err("Input is empty".to_string()),
+        };
+        let mut sites = Vec::new();
+        for (number, line) in lines {
+            let coordinates: Result<Vec<usize>, _> = words(line).map(|c| c.parse::<usize>()).collect();
+            match coordinates {
+                Ok(ref c) if c.len() == 2 && c.iter().all(|&c| c >= 1 && c <= n) => sites.push((c[0], c[1])),
+                Ok(ref c) if c.len() == 2 => return Err(format!("Line {}: site ({}, {}) is outside the {}x{} grid",
+                        number + 1, c[0], c[1], n, n)),
+                _ => return Err(format!("Line {}: expected a row and a column, found '{}'", number + 1, line)),
+            }
+        }
+        Ok(Input { n: n, sites: sites })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Check {
+    IsOpen(usize, usize, bool),
+    IsFull(usize, usize, bool),
+    Percolates(bool),
+}
+
+/// What the grid should look like once the first `step` sites of an input have been opened.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Expectation {
+    pub step: usize,
+    pub check: Check,
+    // where the expectation came from, for reporting failures
+    pub line: usize,
+}
+
+/// Parses an expected-results file. Each line is one of
+///
+/// ```text
+/// STEP is_open I J true|false
+/// STEP is_full I J true|false
+/// STEP percolates true|false
+/// ```
+///
+/// and blank lines and lines starting with `#` are ignored.
+pub fn parse_expectations(s: &str) -> Result<Vec<Expectation>, String> {
+    let mut expectations = Vec::new();
+    for (number, line) in s.lines().enumerate() {
+        let words = words(line).collect::<Vec<&str>>();
+        if words.is_empty() || words[0].starts_with("#") {
+            continue;
+        }
+        let invalid = || format!("Line {}: expected 'STEP is_open|is_full I J BOOL' or 'STEP percolates BOOL', \
+                found '{}'", number + 1, line);
+        let step = try!(words[0].parse::<usize>().map_err(|_| invalid()));
+        let check = match (&words[1..], words.len()) {
+            (rest, 3) if rest[0] == "percolates" => Check::Percolates(try!(rest[1].parse().map_err(|_| invalid()))),
+            (rest, 5) if rest[0] == "is_open" || rest[0] == "is_full" => {
+                let i = try!(rest[1].parse::<usize>().map_err(|_| invalid()));
+                let j = try!(rest[2].parse::<usize>().map_err(|_| invalid()));
+                let expected = try!(rest[3].parse::<bool>().map_err(|_| invalid()));
+                if rest[0] == "is_open" { Check::IsOpen(i, j, expected) } else { Check::IsFull(i, j, expected) }
+            },
+            _ => return Err(invalid()),
+        };
+        expectations.push(Expectation { step: step, check: check, line: number + 1 });
+    }
+    Ok(expectations)
+}
+
+fn words<'a>(line: &'a str) -> Box<Iterator<Item=&'a str> + 'a> {
+    Box::new(line.split(|c: char| c.is_whitespace()).filter(|word| !word.is_empty()))
+}
+
+/// The outcome of replaying an input.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Replay {
+    /// The number of sites from the input which had been opened when the system first percolated, and how many
+    /// distinct sites were open at that point (the input may open a site more than once).
+    pub percolated_at: Option<(usize, usize)>,
+    pub open_sites: usize,
+    /// A description of each expectation which didn't hold.
+    pub failures: Vec<String>,
+}
+
+/// Opens the sites of `input` in order, checking each expectation once its step has been reached.
+pub fn replay(input: &Input, uf: UnionFindKind, expectations: &[Expectation]) -> Replay {
+    let mut perc = Percolation::with_union_find(input.n, uf);
+    let mut percolated_at = None;
+    let mut failures = Vec::new();
+    for step in 0 .. input.sites.len() + 1 {
+        if step > 0 {
+            let (i, j) = input.sites[step - 1];
+            perc.open(i, j);
+            if percolated_at.is_none() && perc.percolates() {
+                percolated_at = Some((step, perc.number_of_open_sites()));
+            }
+        }
+        for expectation in expectations.iter().filter(|expectation| expectation.step == step) {
+            if let Some(failure) = check(&perc, expectation) {
+                failures.push(failure);
+            }
+        }
+    }
+    for expectation in expectations.iter().filter(|expectation| expectation.step > input.sites.len()) {
+        failures.push(format!("Line {}: step {} is past the end of the input, which opens {} sites",
+                expectation.line, expectation.step, input.sites.len()));
+    }
+    Replay { percolated_at: percolated_at, open_sites: perc.number_of_open_sites(), failures: failures }
+}
+
+fn check(perc: &Percolation, expectation: &Expectation) -> Option<String> {
+    let (description, expected, actual) = match expectation.check {
+        Check::IsOpen(i, j, expected) => (format!("is_open({}, {})", i, j), expected, perc.try_is_open(i, j)),
+        Check::IsFull(i, j, expected) => (format!("is_full({}, {})", i, j), expected, perc.try_is_full(i, j)),
+        Check::Percolates(expected) => ("percolates()".to_string(), expected, Ok(perc.percolates())),
+    };
+    let actual = match actual {
+        Ok(actual) => actual,
+        Err(e) => return Some(format!("Line {}: {}", expectation.line, e)),
+    };
+    if expected == actual {
+        None
+    } else {
+        Some(format!("Line {}: after {} sites, expected {} to be {} but it was {}", expectation.line,
+                expectation.step, description, expected, actual))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Check, Expectation, Input, parse_expectations, replay};
+    use unionfind::UnionFindKind;
+
+    fn replay_file(input: &str, expected: &str) -> super::Replay {
+        let input = input.parse::<Input>().unwrap();
+        let expectations = parse_expectations(expected).unwrap();
+        replay(&input, UnionFindKind::WeightedQuickUnion, &expectations[..])
+    }
+
+    #[test]
+    fn inputs_parse_into_a_size_and_sites() {
+        assert_eq!("3\n1 2\n\n 3 3 \n".parse::<Input>(), Ok(Input { n: 3, sites: vec![(1, 2), (3, 3)] }));
+        assert!("".parse::<Input>().is_err());
+        assert!("0\n".parse::<Input>().is_err());
+        assert!("3\n1 4\n".parse::<Input>().is_err());
+        assert!("3\n1\n".parse::<Input>().is_err());
+    }
+
+    #[test]
+    fn expectations_parse_and_skip_comments() {
+        let expectations = parse_expectations("# comment\n\n2 is_full 1 3 false\n4 percolates true\n").unwrap();
+        assert_eq!(expectations, vec![
+            Expectation { step: 2, check: Check::IsFull(1, 3, false), line: 3 },
+            Expectation { step: 4, check: Check::Percolates(true), line: 4 },
+        ]);
+        assert!(parse_expectations("1 percolates maybe").is_err());
+    }
+
+    #[test]
+    fn single_site_inputs() {
+        assert_eq!(replay_file(include_str!("../inputs/input1.txt"), "").percolated_at, Some((1, 1)));
+        assert_eq!(replay_file(include_str!("../inputs/input1-no.txt"), "").percolated_at, None);
+    }
+
+    #[test]
+    fn snake_meets_its_expectations() {
+        let replayed = replay_file(include_str!("../inputs/snake5.txt"), include_str!("../inputs/snake5-expected.txt"));
+        assert_eq!(replayed.failures, Vec::<String>::new());
+        assert_eq!(replayed.percolated_at, Some((9, 9)));
+        assert_eq!(replayed.open_sites, 10);
+    }
+
+    #[test]
+    fn failed_expectations_are_reported() {
+        let replayed = replay_file("2\n1 1\n", "1 percolates true\n1 is_open 3 1 true\n5 percolates false\n");
+        assert_eq!(replayed.failures.len(), 3);
+        assert!(replayed.failures[0].starts_with("Line 1: after 1 sites, expected percolates() to be true"));
+    }
+}
@@ -0,0 +1,114 @@
+use std::f64;
+use lattice::Lattice;
+use percolation::{self, Simulation};
+use progress;
+use stats::PercolationStats;
+
+/// Correlation length exponent ν of 2D percolation, which sets how quickly thresholds on finite grids approach the
+/// threshold of the infinite lattice.
+pub const NU_2D: f64 = 4.0 / 3.0;
+
+/// Fit of the finite-size scaling form `mean(n) = p_c + a n^(-1/ν)` to the mean thresholds of several grid sizes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScalingFit {
+    /// The extrapolated threshold of the infinite lattice.
+    pub p_c: f64,
+    /// Standard error of `p_c`; NaN if there weren't enough sizes to estimate it.
+    pub p_c_error: f64,
+    pub slope: f64,
+}
+
+/// The thresholds of square grids of several sizes, all run like `sim` apart from their size.
+#[derive(Debug)]
+pub struct Sweep {
+    pub sim: Simulation,
+    pub seed: usize,
+    pub jobs: u32,
+    pub nu: f64,
+    pub sizes: Vec<(usize, PercolationStats)>,
+    pub fit: Option<ScalingFit>,
+}
+
+/// Runs `times` trials on an n x n grid for each of `sizes`, and fits the scaling form to the results with
+/// exponent `nu`.
+pub fn sweep(sim: &Simulation, sizes: &[usize], nu: f64, times: usize, jobs: u32, seed: usize) -> Sweep {
+    // after Ctrl-C, the sizes which haven't started are left out rather than run without any trials
+    let results = sizes.iter().take_while(|_| !progress::interrupted()).map(|&n| {
+        let lattice = Lattice::square(n).with_boundary(sim.lattice.boundary);
+        (n, percolation::simulate_multiple(&Simulation { lattice: lattice, ..sim.clone() }, times, jobs, seed))
+    }).collect::<Vec<(usize, PercolationStats)>>();
+    let fit = fit_threshold(&results, nu);
+    Sweep { sim: sim.clone(), seed: seed, jobs: jobs, nu: nu, sizes: results, fit: fit }
+}
+
+/// Fits the scaling form by least squares of the mean thresholds against `n^(-1/ν)`, so that `p_c` is the intercept.
+/// Each size is weighted by the inverse variance of its mean when every size has one, and equally otherwise. Needs
+/// at least two sizes.
+pub fn fit_threshold(sizes: &[(usize, PercolationStats)], nu: f64) -> Option<ScalingFit> {
+    if sizes.len() < 2 {
+        return None;
+    }
+    let points = sizes.iter().map(|&(n, ref stats)| {
+        let x = (n as f64).powf(-1.0 / nu);
+        let standard_error = stats.stddev() as f64 / (stats.trials() as f64).sqrt();
+        (x, stats.mean() as f64, standard_error)
+    }).collect::<Vec<(f64, f64, f64)>>();
+    let weighted = points.iter().all(|&(_, _, error)| error.is_finite() && error > 0.0);
+
+    let (mut s, mut sx, mut sy, mut sxx, mut sxy) = (0.0, 0.0, 0.0, 0.0, 0.0);
+    for &(x, y, error) in points.iter() {
+        let w = if weighted { 1.0 / (error * error) } else { 1.0 };
+        s += w;
+        sx += w * x;
+        sy += w * y;
+        sxx += w * x * x;
+        sxy += w * x * y;
+    }
+    let delta = s * sxx - sx * sx;
+    if delta == 0.0 {
+        // every size was the same, so there's nothing to extrapolate along
+        return None;
+    }
+    let p_c = (sxx * sy - sx * sxy) / delta;
+    let slope = (s * sxy - sx * sy) / delta;
+
+    let p_c_error = if weighted {
+        (sxx / delta).sqrt()
+    } else if points.len() > 2 {
+        // estimate the spread of the means from how far they are from the fitted line
+        let residuals = points.iter().fold(0.0, |sum, &(x, y, _)| sum + (y - p_c - slope * x).powi(2));
+        (residuals / (points.len() - 2) as f64 * sxx / delta).sqrt()
+    } else {
+        f64::NAN
+    };
+    Some(ScalingFit { p_c: p_c, p_c_error: p_c_error, slope: slope })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{NU_2D, fit_threshold};
+    use stats::PercolationStats;
+
+    #[test]
+    fn fit_recovers_the_intercept_of_exact_scaling() {
+        let sizes = [8, 16, 32, 64].iter().map(|&n| {
+            let mean = 0.59 + 0.5 * (n as f64).powf(-1.0 / NU_2D);
+            // symmetric spread around the mean, so every size has the same weight
+            (n, PercolationStats::new(vec![mean as f32 - 0.01, mean as f32 + 0.01]))
+        }).collect::<Vec<(usize, PercolationStats)>>();
+        let fit = fit_threshold(&sizes, NU_2D).unwrap();
+
+        assert!((fit.p_c - 0.59).abs() < 1e-4, "p_c was {}", fit.p_c);
+        assert!((fit.slope - 0.5).abs() < 1e-3, "slope was {}", fit.slope);
+        assert!(fit.p_c_error > 0.0);
+    }
+
+    #[test]
+    fn fit_needs_two_sizes() {
+        assert_eq!(fit_threshold(&[(16, PercolationStats::new(vec![0.6]))], NU_2D), None);
+
+        let fit = fit_threshold(&[(16, PercolationStats::new(vec![0.6])), (32, PercolationStats::new(vec![0.595]))],
+                NU_2D).unwrap();
+        assert!(fit.p_c_error.is_nan());
+    }
+}
@@ -0,0 +1,199 @@
+use std::f32;
+use std::fmt;
+use std::iter;
+
+/// z-score of the two-sided 95% confidence interval of a normal distribution.
+const CONFIDENCE_95: f32 = 1.96;
+
+/// Percolation thresholds gathered over a number of independent trials.
+#[derive(Debug)]
+pub struct PercolationStats {
+    results: Vec<f32>,
+}
+
+impl PercolationStats {
+    pub fn new(results: Vec<f32>) -> PercolationStats {
+        PercolationStats { results: results }
+    }
+
+    pub fn results(&self) -> &[f32] {
+        &self.results[..]
+    }
+
+    pub fn trials(&self) -> usize {
+        self.results.len()
+    }
+
+    pub fn mean(&self) -> f32 {
+        let mut mean = 0f32;
+        for (i, &r) in self.results.iter().enumerate() {
+            mean = (mean * i as f32 + r ) / (i + 1) as f32;
+        }
+        mean
+    }
+
+    /// Sample standard deviation of the thresholds; NaN if there are fewer than 2 trials.
+    pub fn stddev(&self) -> f32 {
+        if self.results.len() < 2 {
+            return f32::NAN;
+        }
+        let mean = self.mean();
+        let sum_of_squares = self.results.iter().fold(0f32, |sum, &r| sum + (r - mean) * (r - mean));
+        (sum_of_squares / (self.results.len() - 1) as f32).sqrt()
+    }
+
+    /// Half the width of the 95% confidence interval of the mean.
+    pub fn confidence_half_width(&self) -> f32 {
+        CONFIDENCE_95 * self.stddev() / (self.results.len() as f32).sqrt()
+    }
+
+    /// Low endpoint of the 95% confidence interval of the mean.
+    pub fn confidence_lo(&self) -> f32 {
+        self.mean() - self.confidence_half_width()
+    }
+
+    /// High endpoint of the 95% confidence interval of the mean.
+    pub fn confidence_hi(&self) -> f32 {
+        self.mean() + self.confidence_half_width()
+    }
+
+    pub fn min(&self) -> f32 {
+        self.results.iter().fold(f32::NAN, |min, &r| r.min(min))
+    }
+
+    pub fn max(&self) -> f32 {
+        self.results.iter().fold(f32::NAN, |max, &r| r.max(max))
+    }
+
+    /// Buckets the thresholds into `bins` equally wide bins between the smallest and largest threshold.
+    pub fn histogram(&self, bins: usize) -> Histogram {
+        assert!(bins > 0, "A histogram needs at least one bin");
+        let (lo, hi) = (self.min(), self.max());
+        let mut counts: Vec<usize> = iter::repeat(0).take(bins).collect();
+        for &r in self.results.iter() {
+            let bin = if hi > lo {
+                ((r - lo) / (hi - lo) * bins as f32) as usize
+            } else {
+                0
+            };
+            // the largest threshold lands exactly on the upper edge, so put it in the last bin
+            counts[if bin < bins { bin } else { bins - 1 }] += 1;
+        }
+        Histogram { lo: lo, hi: hi, counts: counts }
+    }
+}
+
+/// Mean and spread of a stream of results, updated one result at a time (Welford's method) so that checking the
+/// confidence interval after every trial doesn't mean going over all the results again.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RunningStats {
+    count: usize,
+    mean: f64,
+    // sum of squared differences from the mean
+    m2: f64,
+}
+
+impl RunningStats {
+    pub fn push(&mut self, result: f32) {
+        self.count += 1;
+        let delta = result as f64 - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (result as f64 - self.mean);
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Half the width of the 95% confidence interval of the mean; NaN if there are fewer than 2 results.
+    pub fn confidence_half_width(&self) -> f32 {
+        if self.count < 2 {
+            return f32::NAN;
+        }
+        let stddev = (self.m2 / (self.count - 1) as f64).sqrt();
+        (CONFIDENCE_95 as f64 * stddev / (self.count as f64).sqrt()) as f32
+    }
+}
+
+#[derive(Debug)]
+pub struct Histogram {
+    pub lo: f32,
+    pub hi: f32,
+    pub counts: Vec<usize>,
+}
+
+impl Histogram {
+    pub fn bin_width(&self) -> f32 {
+        (self.hi - self.lo) / self.counts.len() as f32
+    }
+}
+
+impl fmt::Display for Histogram {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let max_bar = 50;
+        let largest = self.counts.iter().fold(0, |largest, &count| if count > largest { count } else { largest });
+        for (bin, &count) in self.counts.iter().enumerate() {
+            let bar_length = if largest > 0 { count * max_bar / largest } else { 0 };
+            try!(writeln!(f, "[{:.4}, {:.4}) {:>6} {}",
+                    self.lo + bin as f32 * self.bin_width(),
+                    self.lo + (bin + 1) as f32 * self.bin_width(),
+                    count,
+                    iter::repeat("#").take(bar_length).collect::<String>()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PercolationStats, RunningStats};
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-5, "expected {} but was {}", expected, actual);
+    }
+
+    #[test]
+    fn stats_summarise_results() {
+        let stats = PercolationStats::new(vec![0.5, 0.6, 0.7, 0.6]);
+
+        assert_close(stats.mean(), 0.6);
+        assert_close(stats.stddev(), 0.08164966);
+        assert_close(stats.confidence_lo(), 0.6 - 1.96 * 0.08164966 / 2.0);
+        assert_close(stats.confidence_hi(), 0.6 + 1.96 * 0.08164966 / 2.0);
+        assert_close(stats.min(), 0.5);
+        assert_close(stats.max(), 0.7);
+    }
+
+    #[test]
+    fn stddev_of_a_single_trial_is_undefined() {
+        assert!(PercolationStats::new(vec![0.5]).stddev().is_nan());
+    }
+
+    #[test]
+    fn histogram_puts_every_result_in_a_bin() {
+        let histogram = PercolationStats::new(vec![0.5, 0.52, 0.61, 0.62, 0.7]).histogram(4);
+
+        assert_eq!(histogram.counts, vec![2, 0, 2, 1]);
+        assert_close(histogram.bin_width(), 0.05);
+    }
+
+    #[test]
+    fn histogram_of_identical_results_uses_first_bin() {
+        let histogram = PercolationStats::new(vec![0.6, 0.6]).histogram(3);
+
+        assert_eq!(histogram.counts, vec![2, 0, 0]);
+    }
+
+    #[test]
+    fn running_stats_agree_with_stats_over_all_results() {
+        let results = vec![0.5, 0.6, 0.7, 0.6];
+        let mut running = RunningStats::default();
+        assert!(running.confidence_half_width().is_nan());
+        for &r in results.iter() {
+            running.push(r);
+        }
+
+        assert_eq!(running.count(), 4);
+        assert_close(running.confidence_half_width(), PercolationStats::new(results).confidence_half_width());
+    }
+}
@@ -0,0 +1,318 @@
+use std::cmp::{self, Ordering};
+use std::f32;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::str::FromStr;
+use std::sync::Arc;
+use lattice::{Boundary, Lattice};
+use percolation::{Model, System};
+use rand::Rng;
+
+/// Hands out the elements of a system to open, one at a time.
+pub trait Opener {
+    /// Returns the next element to open given the state of `system`, or None once every element has been handed out.
+    fn next(&mut self, system: &System) -> Option<usize>;
+}
+
+/// Opens elements in an order decided up front.
+struct Ordered {
+    // the elements left to open, the next one last
+    to_open: Vec<usize>,
+}
+
+impl Opener for Ordered {
+    fn next(&mut self, _: &System) -> Option<usize> {
+        self.to_open.pop()
+    }
+}
+
+/// Opens elements in random order, but puts off the ones which would make the system percolate until nothing else is
+/// left. This greedy one-step lookahead only ever avoids percolating with the next element, so it delays percolation
+/// until every remaining element would cause it, which isn't necessarily the latest percolation of any order. Opening
+/// elements only ever joins clusters, so an element which would make the system percolate still would later on, and
+/// a single pass through the elements is enough.
+struct Adversarial {
+    to_open: Vec<usize>,
+    put_off: Vec<usize>,
+}
+
+impl Opener for Adversarial {
+    fn next(&mut self, system: &System) -> Option<usize> {
+        while let Some(element) = self.to_open.pop() {
+            if system.would_percolate(element) {
+                self.put_off.push(element);
+            } else {
+                return Some(element);
+            }
+        }
+        self.put_off.pop()
+    }
+}
+
+/// How much more likely each site of a 2D lattice is to open than the others, for inhomogeneous media.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProbabilityMap {
+    /// Where the map was loaded from, to record alongside results.
+    pub name: String,
+    pub rows: usize,
+    pub cols: usize,
+    /// The probability of each site, row by row.
+    pub probabilities: Vec<f32>,
+}
+
+impl ProbabilityMap {
+    /// Parses a map with a line of whitespace separated probabilities between 0 and 1 for each row of sites. Lines
+    /// starting with `#` are comments.
+    pub fn parse(name: &str, s: &str) -> Result<ProbabilityMap, String> {
+        let (mut rows, mut cols, mut probabilities) = (0, 0, Vec::new());
+        for (number, line) in s.lines().enumerate() {
+            let words = line.split(|c: char| c.is_whitespace()).filter(|word| !word.is_empty())
+                .collect::<Vec<&str>>();
+            if words.is_empty() || words[0].starts_with("#") {
+                continue;
+            }
+            if rows > 0 && words.len() != cols {
+                return Err(format!("Line {}: expected {} probabilities like the rows before, found {}", number + 1,
+                    cols, words.len()));
+            }
+            for word in words.iter() {
+                match word.parse::<f32>() {
+                    Ok(p) if p >= 0.0 && p <= 1.0 => probabilities.push(p),
+                    _ => return Err(format!("Line {}: expected a probability between 0 and 1, found '{}'", number + 1,
+                        word)),
+                }
+            }
+            rows += 1;
+            cols = words.len();
+        }
+        if !probabilities.iter().any(|&p| p > 0.0) {
+            return Err("A probability map needs at least one site with a positive probability".to_string());
+        }
+        Ok(ProbabilityMap { name: name.to_string(), rows: rows, cols: cols, probabilities: probabilities })
+    }
+}
+
+/// The order elements open in. Apart from the uniform strategy, these only apply to the lattice models.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Strategy {
+    /// Every order is equally likely.
+    Uniform,
+    /// Elements open in order of their number, so sites open row by row.
+    RowMajor,
+    /// Sites open in order of a random field averaged over the sites within this many steps along each axis, so that
+    /// nearby sites tend to open at around the same time.
+    Correlated(usize),
+    /// Elements open in random order, except that an element which would make the system percolate is put off while
+    /// any other is left.
+    Adversarial,
+    /// Site `s` with probability `p` opens once a level rising from 0 reaches `u / p`, for a uniformly random `u`: at
+    /// level `t`, each site is open with probability `t p`. Sites with probability 0 open last.
+    Map(Arc<ProbabilityMap>),
+}
+
+/// Radius of a correlated strategy given without one.
+const DEFAULT_CORRELATION_RADIUS: usize = 2;
+
+impl Strategy {
+    /// Like parsing, but also accepts `map:FILE` to load a probability map from a file.
+    pub fn load(s: &str) -> Result<Strategy, String> {
+        if s.starts_with("map:") {
+            let filename = &s["map:".len()..];
+            let mut contents = String::new();
+            try!(File::open(filename).and_then(|mut file| file.read_to_string(&mut contents))
+                .map_err(|e| format!("Failed to read {}: {}", filename, e)));
+            ProbabilityMap::parse(filename, &contents).map(|map| Strategy::Map(Arc::new(map)))
+                .map_err(|e| format!("Failed to parse {}: {}", filename, e))
+        } else {
+            s.parse::<Strategy>()
+        }
+    }
+
+    /// Checks that the strategy can open the elements of `model` on `lattice`.
+    pub fn check(&self, lattice: &Lattice, model: Model) -> Result<(), String> {
+        match *self {
+            Strategy::Uniform | Strategy::RowMajor => Ok(()),
+            Strategy::Adversarial if lattice.boundary == Boundary::Torus =>
+                Err("The adversarial strategy can't look ahead on a torus".to_string()),
+            Strategy::Adversarial => Ok(()),
+            _ if !model.opens_sites() => Err(format!("The {} strategy only opens sites", self)),
+            Strategy::Map(ref map) if !lattice.is_2d() || map.rows != lattice.rows || map.cols != lattice.cols =>
+                Err(format!("The {}x{} probability map {} doesn't fit a {} lattice", map.rows, map.cols, map.name,
+                    lattice)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns an opener for the `elements` elements of a system on `lattice`, which must have been checked with
+    /// `check`.
+    pub fn opener<R: Rng>(&self, lattice: &Lattice, elements: usize, rng: &mut R) -> Box<Opener> {
+        match *self {
+            Strategy::Uniform => uniform(elements, rng),
+            Strategy::RowMajor => Box::new(Ordered { to_open: (0..elements).rev().collect() }),
+            Strategy::Correlated(radius) => {
+                let field = (0..elements).map(|_| rng.gen::<f32>()).collect::<Vec<f32>>();
+                Box::new(Ordered { to_open: descending(&smooth(lattice, &field, radius)) })
+            },
+            Strategy::Adversarial => Box::new(Adversarial { to_open: shuffled(elements, rng), put_off: Vec::new() }),
+            Strategy::Map(ref map) => {
+                let levels = map.probabilities.iter().map(|&p| {
+                    let u = rng.gen::<f32>();
+                    if p > 0.0 { u / p } else { f32::INFINITY }
+                }).collect::<Vec<f32>>();
+                Box::new(Ordered { to_open: descending(&levels) })
+            },
+        }
+    }
+}
+
+/// Returns an opener for `elements` elements in uniformly random order, for systems without a lattice as well.
+pub fn uniform<R: Rng>(elements: usize, rng: &mut R) -> Box<Opener> {
+    Box::new(Ordered { to_open: shuffled(elements, rng) })
+}
+
+fn shuffled<R: Rng>(elements: usize, rng: &mut R) -> Vec<usize> {
+    let mut to_open: Vec<usize> = (0..elements).collect();
+    rng.shuffle(to_open.as_mut_slice());
+    to_open
+}
+
+/// Returns the elements in descending order of `keys`, so that popping them gives the smallest key first.
+fn descending(keys: &[f32]) -> Vec<usize> {
+    let mut elements: Vec<usize> = (0..keys.len()).collect();
+    elements.sort_by(|&a, &b| match keys[b].partial_cmp(&keys[a]).unwrap() { // safe: keys are never NaN
+        Ordering::Equal => b.cmp(&a),
+        ordering => ordering,
+    });
+    elements
+}
+
+/// Averages `field` over the sites within `radius` steps along each axis of `lattice`, one axis at a time. The average
+/// is cut off at the edges of the lattice rather than wrapping around.
+fn smooth(lattice: &Lattice, field: &[f32], radius: usize) -> Vec<f32> {
+    let sizes = [lattice.rows, lattice.cols, lattice.layers];
+    let mut field = field.to_vec();
+    for axis in 0..3 {
+        let previous = field.clone();
+        for site in 0..field.len() {
+            let (row, col, layer) = lattice.coordinates(site);
+            let mut at = [row, col, layer];
+            let (lo, hi) = (at[axis].saturating_sub(radius), cmp::min(at[axis] + radius, sizes[axis] - 1));
+            let mut total = 0.0;
+            for position in lo .. hi + 1 {
+                at[axis] = position;
+                total += previous[lattice.index(at[0], at[1], at[2])];
+            }
+            field[site] = total / (hi - lo + 1) as f32;
+        }
+    }
+    field
+}
+
+impl fmt::Display for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Strategy::Uniform => write!(f, "uniform"),
+            Strategy::RowMajor => write!(f, "row-major"),
+            Strategy::Correlated(radius) => write!(f, "correlated:{}", radius),
+            Strategy::Adversarial => write!(f, "adversarial"),
+            Strategy::Map(ref map) => write!(f, "map:{}", map.name),
+        }
+    }
+}
+
+/// Parses every strategy except for probability maps, which have to be loaded with `Strategy::load`.
+impl FromStr for Strategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Strategy, String> {
+        match s {
+            "uniform" => Ok(Strategy::Uniform),
+            "row-major" => Ok(Strategy::RowMajor),
+            "correlated" => Ok(Strategy::Correlated(DEFAULT_CORRELATION_RADIUS)),
+            "adversarial" => Ok(Strategy::Adversarial),
+            _ if s.starts_with("correlated:") => s["correlated:".len()..].parse::<usize>().map(Strategy::Correlated)
+                .map_err(|_| format!("Invalid correlation radius in '{}'", s)),
+            _ => Err(format!("Unknown strategy '{}' (expected one of: uniform, row-major, correlated[:RADIUS], \
+                adversarial, map:FILE)", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use super::{ProbabilityMap, Strategy};
+    use lattice::{Boundary, Lattice};
+    use percolation::{self, Model, Percolation, Simulation, trial_rng};
+    use unionfind::UnionFindKind;
+
+    /// Returns every site of `lattice` in the order `strategy` opens them, opening each one as it's handed out.
+    fn order(strategy: &Strategy, lattice: Lattice) -> Vec<usize> {
+        let mut perc = Percolation::with_lattice(lattice, UnionFindKind::WeightedQuickUnion);
+        let mut opener = strategy.opener(&lattice, lattice.site_count(), &mut trial_rng(3, 0));
+        let mut sites = Vec::new();
+        while let Some(site) = opener.next(&perc) {
+            perc.open_site(site);
+            sites.push(site);
+        }
+        sites
+    }
+
+    #[test]
+    fn every_strategy_opens_each_site_once() {
+        let map = ProbabilityMap::parse("map", "0.5 1 0 0.25\n0.1 0.1 0.9 1\n1 0 0 0\n").unwrap();
+        let strategies = [Strategy::Uniform, Strategy::RowMajor, Strategy::Correlated(1), Strategy::Adversarial,
+            Strategy::Map(Arc::new(map))];
+        for strategy in strategies.iter() {
+            let mut sites = order(strategy, Lattice::rectangle(3, 4));
+            sites.sort();
+            assert_eq!(sites, (0..12).collect::<Vec<usize>>());
+        }
+        assert_eq!(order(&Strategy::RowMajor, Lattice::rectangle(3, 4)), (0..12).collect::<Vec<usize>>());
+    }
+
+    #[test]
+    fn adversarial_opening_percolates_no_sooner_than_uniform() {
+        for trial in 0..20 {
+            let uniform = Simulation::new(6);
+            let adversarial = Simulation { strategy: Strategy::Adversarial, ..Simulation::new(6) };
+            let threshold = percolation::simulate(&adversarial, &mut trial_rng(5, trial));
+            assert!(threshold >= percolation::simulate(&uniform, &mut trial_rng(5, trial)));
+        }
+    }
+
+    #[test]
+    fn sites_without_a_chance_open_last() {
+        let map = ProbabilityMap::parse("map", "# two rows\n1 0\n\n0.5 0.5\n").unwrap();
+        assert_eq!((map.rows, map.cols), (2, 2));
+        assert_eq!(*order(&Strategy::Map(Arc::new(map)), Lattice::square(2)).last().unwrap(), 1);
+
+        assert!(ProbabilityMap::parse("map", "1 1\n1\n").unwrap_err().contains("Line 2"));
+        assert!(ProbabilityMap::parse("map", "1 1.5\n").unwrap_err().contains("'1.5'"));
+        assert!(ProbabilityMap::parse("map", "0 0\n").is_err());
+    }
+
+    #[test]
+    fn strategies_are_checked_against_the_lattice_and_model() {
+        let map = Strategy::Map(Arc::new(ProbabilityMap::parse("map", "1 1\n1 1\n").unwrap()));
+        let torus = Lattice::square(2).with_boundary(Boundary::Torus);
+        assert_eq!(map.check(&Lattice::square(2), Model::Site), Ok(()));
+        assert!(map.check(&Lattice::square(3), Model::Site).is_err());
+        assert!(map.check(&Lattice::square(2), Model::Bond).is_err());
+        assert!(Strategy::Correlated(2).check(&Lattice::square(2), Model::Bond).is_err());
+        assert!(Strategy::Adversarial.check(&torus, Model::Site).is_err());
+        assert_eq!(Strategy::RowMajor.check(&torus, Model::Bond), Ok(()));
+    }
+
+    #[test]
+    fn strategies_round_trip_through_strings() {
+        for s in ["uniform", "row-major", "correlated:3", "adversarial"].iter() {
+            assert_eq!(s.parse::<Strategy>().unwrap().to_string(), *s);
+        }
+        assert_eq!("correlated".parse::<Strategy>(), Ok(Strategy::Correlated(2)));
+        assert!("correlated:x".parse::<Strategy>().is_err());
+        assert!("map:probabilities.txt".parse::<Strategy>().is_err());
+        assert!(Strategy::load("map:no-such-file.txt").unwrap_err().contains("no-such-file.txt"));
+    }
+}
@@ -1,81 +1,380 @@
+use std::cell::Cell;
+use std::fmt;
 use std::iter;
-use conversions::AsUsizeConverter;
+use std::mem;
+use std::str::FromStr;
+use std::u32;
+use conversions::{AsUsizeConverter, TryU32Converter};
+
+/// An unsigned integer type which union-finds can store their node ids and component sizes in. Narrower types halve
+/// the memory of a union-find, but limit how many nodes it can have.
+pub trait Index: Copy + Eq + fmt::Debug + AsUsizeConverter {
+    /// Returns the largest number of nodes a union-find can number with this type.
+    fn max_len() -> usize;
+
+    /// Converts `value` to this type, or panics if it doesn't fit.
+    fn from_usize(value: usize) -> Self;
+}
+
+impl Index for u32 {
+    fn max_len() -> usize {
+        u32::MAX as usize
+    }
+
+    fn from_usize(value: usize) -> u32 {
+        value.try_u32()
+    }
+}
+
+impl Index for u64 {
+    fn max_len() -> usize {
+        // every usize fits in a u64
+        !0
+    }
+
+    fn from_usize(value: usize) -> u64 {
+        value as u64
+    }
+}
 
 pub trait UnionFind {
-    fn union(&mut self, p: u32, q: u32);
-    fn connected(&self, p: u32, q: u32) -> bool;
+    fn union(&mut self, p: usize, q: usize);
+
+    /// Returns the canonical node of the component containing `p`.
+    fn find(&self, p: usize) -> usize;
+
+    /// Returns the number of components.
+    fn count(&self) -> usize;
+
+    /// Returns the number of nodes, connected or not.
+    fn len(&self) -> usize;
+
+    fn connected(&self, p: usize, q: usize) -> bool {
+        self.find(p) == self.find(q)
+    }
+
+    /// Returns the number of nodes in the component containing `p`. Constant time for the union-finds which track
+    /// sizes (see `UnionFindKind::tracks_sizes`), and linear in the number of nodes for the others.
+    fn component_size(&self, p: usize) -> usize {
+        self.members(p).len()
+    }
+
+    /// Returns every node in the component containing `p`, in ascending order. Linear in the number of nodes.
+    fn members(&self, p: usize) -> Vec<usize> {
+        let root = self.find(p);
+        (0 .. self.len()).filter(|&q| self.find(q) == root).collect()
+    }
+
+    /// Returns the number of bytes the nodes take up on the heap.
+    fn memory_usage(&self) -> usize;
+}
+
+/// The union-find implementations available, so that callers can pick one at runtime.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnionFindKind {
+    QuickFind,
+    QuickUnion,
+    WeightedQuickUnion,
+    WeightedQuickUnionPathCompression,
+}
+
+impl UnionFindKind {
+    pub fn all() -> Vec<UnionFindKind> {
+        vec![UnionFindKind::QuickFind, UnionFindKind::QuickUnion,
+            UnionFindKind::WeightedQuickUnion, UnionFindKind::WeightedQuickUnionPathCompression]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            UnionFindKind::QuickFind => "quick-find",
+            UnionFindKind::QuickUnion => "quick-union",
+            UnionFindKind::WeightedQuickUnion => "weighted",
+            UnionFindKind::WeightedQuickUnionPathCompression => "weighted-compressed",
+        }
+    }
+
+    /// Whether `component_size` takes constant time, because the union-find keeps the size of every component to
+    /// weight its unions by. The unweighted ones have to count the members of a component instead.
+    pub fn tracks_sizes(&self) -> bool {
+        match *self {
+            UnionFindKind::QuickFind | UnionFindKind::QuickUnion => false,
+            UnionFindKind::WeightedQuickUnion | UnionFindKind::WeightedQuickUnionPathCompression => true,
+        }
+    }
+
+    /// Creates a union-find of `size` nodes, numbered with u32 if they fit and with u64 otherwise.
+    pub fn create(&self, size: usize) -> Box<UnionFind> {
+        if size <= u32::max_len() {
+            self.create_with_index::<u32>(size)
+        } else {
+            self.create_with_index::<u64>(size)
+        }
+    }
+
+    /// Returns the number of bytes a union-find of `size` nodes from `create` would take up on the heap, without
+    /// creating one.
+    pub fn memory_usage_of(&self, size: usize) -> usize {
+        let index = if size <= u32::max_len() { mem::size_of::<u32>() } else { mem::size_of::<u64>() };
+        let arrays = if self.tracks_sizes() { 2 } else { 1 };
+        arrays * size * index
+    }
+
+    /// Creates a union-find of `size` nodes numbered with `I`, which has to be able to number them all.
+    pub fn create_with_index<I: Index + 'static>(&self, size: usize) -> Box<UnionFind> {
+        match *self {
+            UnionFindKind::QuickFind => Box::new(QuickFindUF::<I>::new(size)),
+            UnionFindKind::QuickUnion => Box::new(QuickUnionUF::<I>::new(size)),
+            UnionFindKind::WeightedQuickUnion => Box::new(WeightedQuickUnionUF::<I>::new(size)),
+            UnionFindKind::WeightedQuickUnionPathCompression =>
+                Box::new(WeightedQuickUnionPathCompressionUF::<I>::new(size)),
+        }
+    }
+}
+
+impl fmt::Display for UnionFindKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for UnionFindKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<UnionFindKind, String> {
+        UnionFindKind::all().into_iter().find(|kind| kind.name() == s).ok_or_else(|| {
+            let names = UnionFindKind::all().iter().map(|kind| kind.name()).collect::<Vec<&str>>();
+            format!("Unknown union-find '{}' (expected one of: {})", s, names.connect(", "))
+        })
+    }
+}
+
+/// Returns the ids `0 .. size` as `I`, after checking that `I` can number that many nodes.
+fn identity<I: Index>(size: usize) -> Vec<I> {
+    assert!(size <= I::max_len(), "{} nodes are too many for the index type", size);
+    (0 .. size).map(|p| I::from_usize(p)).collect()
+}
+
+/// Quick-find: `connected` is a single lookup, but `union` relabels every node of one component.
+#[derive(Debug)]
+pub struct QuickFindUF<I = u32> {
+    id: Vec<I>,
+    count: usize,
+}
+
+impl<I: Index> QuickFindUF<I> {
+    pub fn new(size: usize) -> QuickFindUF<I> {
+        QuickFindUF { id: identity(size), count: size }
+    }
+}
+
+impl<I: Index> UnionFind for QuickFindUF<I> {
+    fn union(&mut self, p: usize, q: usize) {
+        let p_id = self.id[p];
+        let q_id = self.id[q];
+        if p_id != q_id {
+            for id in self.id.iter_mut() {
+                if *id == p_id {
+                    *id = q_id;
+                }
+            }
+            self.count -= 1;
+        }
+    }
+
+    fn find(&self, p: usize) -> usize {
+        self.id[p].as_usize()
+    }
+
+    fn count(&self) -> usize {
+        self.count
+    }
+
+    fn len(&self) -> usize {
+        self.id.len()
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.id.capacity() * mem::size_of::<I>()
+    }
 }
 
 #[derive(Debug)]
-pub struct QuickUnionUF {
-    id: Vec<u32>,
+pub struct QuickUnionUF<I = u32> {
+    id: Vec<I>,
+    count: usize,
 }
 
-impl QuickUnionUF {
-    #![allow(dead_code)]
-    pub fn new(size: u32) -> QuickUnionUF {
-        QuickUnionUF { id: (0u32..size).collect() }
+impl<I: Index> QuickUnionUF<I> {
+    pub fn new(size: usize) -> QuickUnionUF<I> {
+        QuickUnionUF { id: identity(size), count: size }
     }
 
-    fn root(&self, mut i: u32) -> u32 {
-        while i != self.id[i.as_usize()] {
-            i = self.id[i.as_usize()];
+    fn root(&self, mut i: usize) -> usize {
+        while i != self.id[i].as_usize() {
+            i = self.id[i].as_usize();
         }
         i
     }
 }
 
-impl UnionFind for QuickUnionUF {
-    fn union(&mut self, p: u32, q: u32) {
+impl<I: Index> UnionFind for QuickUnionUF<I> {
+    fn union(&mut self, p: usize, q: usize) {
         let i = self.root(p);
         let j = self.root(q);
-        self.id[i.as_usize()] = j;
+        if i != j {
+            self.id[i] = I::from_usize(j);
+            self.count -= 1;
+        }
     }
 
-    fn connected(&self, p: u32, q: u32) -> bool {
-        self.root(p) == self.root(q)
+    fn find(&self, p: usize) -> usize {
+        self.root(p)
+    }
+
+    fn count(&self) -> usize {
+        self.count
+    }
+
+    fn len(&self) -> usize {
+        self.id.len()
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.id.capacity() * mem::size_of::<I>()
     }
 }
 
 #[derive(Debug)]
-pub struct WeightedQuickUnionUF {
-    id: Vec<u32>,
-    sz: Vec<u32>,
+pub struct WeightedQuickUnionUF<I = u32> {
+    id: Vec<I>,
+    sz: Vec<I>,
+    count: usize,
 }
 
-impl WeightedQuickUnionUF {
-    pub fn new(size: u32) -> WeightedQuickUnionUF {
+impl<I: Index> WeightedQuickUnionUF<I> {
+    pub fn new(size: usize) -> WeightedQuickUnionUF<I> {
         WeightedQuickUnionUF {
-            id: (0u32..size).collect(),
-            sz: iter::repeat(1u32).take(size.as_usize()).collect(),
+            id: identity(size),
+            sz: iter::repeat(I::from_usize(1)).take(size).collect(),
+            count: size,
         }
     }
 
-    fn root(&self, mut i: u32) -> u32 {
-        while i != self.id[i.as_usize()] {
-            i = self.id[i.as_usize()];
+    fn root(&self, mut i: usize) -> usize {
+        while i != self.id[i].as_usize() {
+            i = self.id[i].as_usize();
         }
         i
     }
 }
 
-impl UnionFind for WeightedQuickUnionUF {
-    fn union(&mut self, p: u32, q: u32) {
+impl<I: Index> UnionFind for WeightedQuickUnionUF<I> {
+    fn union(&mut self, p: usize, q: usize) {
+        let i = self.root(p);
+        let j = self.root(q);
+        if i != j {
+            let size = I::from_usize(self.sz[i].as_usize() + self.sz[j].as_usize());
+            if self.sz[i].as_usize() < self.sz[j].as_usize() {
+                self.id[i] = I::from_usize(j);
+                self.sz[j] = size;
+            } else {
+                self.id[j] = I::from_usize(i);
+                self.sz[i] = size;
+            }
+            self.count -= 1;
+        }
+    }
+
+    fn find(&self, p: usize) -> usize {
+        self.root(p)
+    }
+
+    fn count(&self) -> usize {
+        self.count
+    }
+
+    fn len(&self) -> usize {
+        self.id.len()
+    }
+
+    fn component_size(&self, p: usize) -> usize {
+        self.sz[self.root(p)].as_usize()
+    }
+
+    fn memory_usage(&self) -> usize {
+        (self.id.capacity() + self.sz.capacity()) * mem::size_of::<I>()
+    }
+}
+
+/// Weighted quick-union which also points every node visited by `root` directly at the root, so that trees stay
+/// almost flat. The ids live in `Cell`s so that queries through `&self` can still compress paths.
+#[derive(Debug)]
+pub struct WeightedQuickUnionPathCompressionUF<I = u32> {
+    id: Vec<Cell<I>>,
+    sz: Vec<I>,
+    count: usize,
+}
+
+impl<I: Index> WeightedQuickUnionPathCompressionUF<I> {
+    pub fn new(size: usize) -> WeightedQuickUnionPathCompressionUF<I> {
+        WeightedQuickUnionPathCompressionUF {
+            id: identity(size).into_iter().map(Cell::new).collect(),
+            sz: iter::repeat(I::from_usize(1)).take(size).collect(),
+            count: size,
+        }
+    }
+
+    fn root(&self, i: usize) -> usize {
+        let mut root = i;
+        while root != self.id[root].get().as_usize() {
+            root = self.id[root].get().as_usize();
+        }
+
+        let mut current = i;
+        while current != root {
+            let next = self.id[current].get().as_usize();
+            self.id[current].set(I::from_usize(root));
+            current = next;
+        }
+        root
+    }
+}
+
+impl<I: Index> UnionFind for WeightedQuickUnionPathCompressionUF<I> {
+    fn union(&mut self, p: usize, q: usize) {
         let i = self.root(p);
         let j = self.root(q);
         if i != j {
-            if self.sz[i.as_usize()] < self.sz[j.as_usize()] {
-                self.id[i.as_usize()] = j;
-                self.sz[j.as_usize()] += self.sz[i.as_usize()];
+            let size = I::from_usize(self.sz[i].as_usize() + self.sz[j].as_usize());
+            if self.sz[i].as_usize() < self.sz[j].as_usize() {
+                self.id[i].set(I::from_usize(j));
+                self.sz[j] = size;
             } else {
-                self.id[j.as_usize()] = i;
-                self.sz[i.as_usize()] += self.sz[j.as_usize()];
+                self.id[j].set(I::from_usize(i));
+                self.sz[i] = size;
             }
+            self.count -= 1;
         }
     }
 
-    fn connected(&self, p: u32, q: u32) -> bool {
-        self.root(p) == self.root(q)
+    fn find(&self, p: usize) -> usize {
+        self.root(p)
+    }
+
+    fn count(&self) -> usize {
+        self.count
+    }
+
+    fn len(&self) -> usize {
+        self.id.len()
+    }
+
+    fn component_size(&self, p: usize) -> usize {
+        self.sz[self.root(p)].as_usize()
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.id.capacity() * mem::size_of::<Cell<I>>() + self.sz.capacity() * mem::size_of::<I>()
     }
 }
 
@@ -83,18 +382,37 @@ impl UnionFind for WeightedQuickUnionUF {
 mod tests {
     use rand;
     use rand::Rng;
+    use std::u32;
     use quickcheck::{StdGen, QuickCheck};
-    use super::super::conversions::{AsUsizeConverter, TryU32Converter};
-    use super::UnionFind;
-    use super::{QuickUnionUF, WeightedQuickUnionUF};
+    use super::{Index, UnionFind};
+    use super::{QuickFindUF, QuickUnionUF, WeightedQuickUnionUF, WeightedQuickUnionPathCompressionUF, UnionFindKind};
+
+    #[test]
+    fn quickfind_connecting_nodes_works() {
+        fn connecting_nodes_works(sizes: Vec<u32>) -> bool {
+            let (node_count, nodes_to_union, expected_groups) = generate_unions(&sizes);
+
+            let mut qu = QuickFindUF::<u32>::new(node_count);
+            if !matches_connection_state(&qu, &(0 .. node_count).map(|node| vec![node]).collect()) {
+                return false;
+            }
+
+            for &(p, q) in nodes_to_union.iter() {
+                qu.union(p, q);
+            }
+            matches_connection_state(&qu, &expected_groups)
+        }
+        QuickCheck::new().gen(StdGen::new(rand::thread_rng(), 25)) // generate vecs with max size 25
+            .quickcheck(connecting_nodes_works as fn(Vec<u32>) -> bool);
+    }
 
     #[test]
     fn quickunion_connecting_nodes_works() {
         fn connecting_nodes_works(sizes: Vec<u32>) -> bool {
             let (node_count, nodes_to_union, expected_groups) = generate_unions(&sizes);
 
-            let mut qu = QuickUnionUF::new(node_count);
-            if !matches_connection_state(&qu, &(0u32 .. node_count).map(|node| vec![node]).collect()) {
+            let mut qu = QuickUnionUF::<u32>::new(node_count);
+            if !matches_connection_state(&qu, &(0 .. node_count).map(|node| vec![node]).collect()) {
                 return false;
             }
 
@@ -114,8 +432,8 @@ mod tests {
         fn connecting_nodes_works(sizes: Vec<u32>) -> bool {
             let (node_count, nodes_to_union, expected_groups) = generate_unions(&sizes);
 
-            let mut qu = WeightedQuickUnionUF::new(node_count);
-            if !matches_connection_state(&qu, &(0u32 .. node_count).map(|node| vec![node]).collect()) {
+            let mut qu = WeightedQuickUnionUF::<u32>::new(node_count);
+            if !matches_connection_state(&qu, &(0 .. node_count).map(|node| vec![node]).collect()) {
                 return false;
             }
 
@@ -129,32 +447,106 @@ mod tests {
             .quickcheck(connecting_nodes_works as fn(Vec<u32>) -> bool);
     }
 
+    #[test]
+    fn weighted_quickunion_path_compression_connecting_nodes_works() {
+        fn connecting_nodes_works(sizes: Vec<u32>) -> bool {
+            let (node_count, nodes_to_union, expected_groups) = generate_unions(&sizes);
+
+            let mut qu = WeightedQuickUnionPathCompressionUF::<u32>::new(node_count);
+            if !matches_connection_state(&qu, &(0 .. node_count).map(|node| vec![node]).collect()) {
+                return false;
+            }
+
+            for &(p, q) in nodes_to_union.iter() {
+                qu.union(p, q);
+            }
+            matches_connection_state(&qu, &expected_groups)
+        }
+        QuickCheck::new().gen(StdGen::new(rand::thread_rng(), 25)) // generate vecs with max size 25
+            .quickcheck(connecting_nodes_works as fn(Vec<u32>) -> bool);
+    }
+
+    #[test]
+    fn components_are_counted_sized_and_listed() {
+        fn components_are_reported(sizes: Vec<u32>) -> bool {
+            let (node_count, nodes_to_union, expected_groups) = generate_unions(&sizes);
+
+            UnionFindKind::all().into_iter().all(|kind| {
+                // both index types have to behave the same
+                vec![kind.create_with_index::<u32>(node_count), kind.create_with_index::<u64>(node_count)]
+                        .into_iter().all(|mut qu| {
+                    for &(p, q) in nodes_to_union.iter() {
+                        qu.union(p, q);
+                    }
+                    qu.len() == node_count && qu.count() == expected_groups.len() &&
+                        expected_groups.iter().all(|group| group.iter().all(|&p| {
+                            qu.find(p) == qu.find(group[0]) &&
+                                qu.component_size(p) == group.len() &&
+                                &qu.members(p) == group
+                        }))
+                })
+            })
+        }
+        QuickCheck::new().gen(StdGen::new(rand::thread_rng(), 25)) // generate vecs with max size 25
+            .quickcheck(components_are_reported as fn(Vec<u32>) -> bool);
+    }
+
+    #[test]
+    fn index_types_convert_nodes_they_can_number() {
+        assert_eq!(<u32 as Index>::max_len(), u32::MAX as usize);
+        assert_eq!(<u64 as Index>::from_usize(u32::MAX as usize + 1), u32::MAX as u64 + 1);
+
+        let mut qu = UnionFindKind::WeightedQuickUnion.create_with_index::<u64>(3);
+        qu.union(0, 2);
+        assert_eq!((qu.find(0), qu.component_size(2), qu.count()), (qu.find(2), 2, 2));
+        // ids and sizes for each node, at twice the width of u32
+        assert_eq!(qu.memory_usage(), 2 * 3 * 8);
+        assert_eq!(UnionFindKind::WeightedQuickUnion.create(3).memory_usage(), 2 * 3 * 4);
+        assert_eq!(UnionFindKind::QuickFind.memory_usage_of(3), 3 * 4);
+    }
+
+    #[test]
+    #[should_panic]
+    fn index_types_refuse_nodes_they_cannot_number() {
+        <u32 as Index>::from_usize(u32::MAX as usize + 1);
+    }
+
+    #[test]
+    fn union_find_kind_round_trips_through_its_name() {
+        for kind in UnionFindKind::all() {
+            assert_eq!(kind.name().parse::<UnionFindKind>(), Ok(kind));
+        }
+        assert!("quick-sort".parse::<UnionFindKind>().is_err());
+        let tracking = UnionFindKind::all().into_iter().filter(|kind| kind.tracks_sizes()).collect::<Vec<_>>();
+        assert_eq!(tracking, vec![UnionFindKind::WeightedQuickUnion, UnionFindKind::WeightedQuickUnionPathCompression]);
+    }
+
     /// Given a list of group sizes, returns the number of nodes, the unions to make, and the final expected groups.
     /// Current implementation limitations:
     /// - the unions & their ordering is currently non-deterministic
     /// - the final connected groups will consist of consequtive numbers
-    fn generate_unions(sizes: &Vec<u32>) -> (u32, Vec<(u32, u32)>, Vec<Vec<u32>>) {
+    fn generate_unions(sizes: &Vec<u32>) -> (usize, Vec<(usize, usize)>, Vec<Vec<usize>>) {
         use std::cmp;
 
         // set some constraints to avoid having this property take forever
         let max_size_per_group = 1001;
         let max_node_count = 5000;
 
-        let mut node_count = 0u32;
-        let mut expected_groups: Vec<Vec<u32>> = Vec::with_capacity(sizes.len());
+        let mut node_count = 0;
+        let mut expected_groups: Vec<Vec<usize>> = Vec::with_capacity(sizes.len());
         for &size in sizes.iter() {
-            let limited_size = cmp::min(size % max_size_per_group, max_node_count);
+            let limited_size = cmp::min(size % max_size_per_group, max_node_count) as usize;
             if limited_size > 0 {
-                let nodes = (node_count .. (node_count + limited_size)).collect::<Vec<u32>>();
+                let nodes = (node_count .. (node_count + limited_size)).collect::<Vec<usize>>();
                 node_count += limited_size;
                 expected_groups.push(nodes);
             }
         }
         // println!("expected_groups has {} nodes: {:?}", node_count, expected_groups);
 
-        let nodes_to_union: Vec<(u32, u32)> = {
+        let nodes_to_union: Vec<(usize, usize)> = {
             let mut rng = rand::thread_rng(); // TODO use http://doc.rust-lang.org/std/rand/trait.SeedableRng.html
-            let mut unions = Vec::with_capacity(node_count.as_usize());
+            let mut unions = Vec::with_capacity(node_count);
             for nodes in expected_groups.iter() {
                 let mut shuffled_nodes = nodes.clone();
                 rng.shuffle(shuffled_nodes.as_mut_slice());
@@ -173,14 +565,14 @@ mod tests {
         (node_count, nodes_to_union, expected_groups)
     }
 
-    fn matches_connection_state(qu: &UnionFind, node_groups: &Vec<Vec<u32>>) -> bool {
+    fn matches_connection_state(qu: &UnionFind, node_groups: &Vec<Vec<usize>>) -> bool {
         use std::collections::HashMap;
 
-        let mut expected_node_groups = HashMap::<u32, u32>::new();
+        let mut expected_node_groups = HashMap::<usize, usize>::new();
         let mut all_nodes = Vec::new();
         for (group_num, nodes) in node_groups.iter().enumerate() {
             for node in nodes.iter() {
-                expected_node_groups.insert(*node, group_num.try_u32());
+                expected_node_groups.insert(*node, group_num);
                 all_nodes.push(node);
             }
         };
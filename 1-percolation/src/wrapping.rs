@@ -0,0 +1,133 @@
+use std::iter;
+use std::mem;
+use lattice::Step;
+
+/// Which directions some cluster wraps all the way around a periodic lattice in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Wrapping {
+    pub vertically: bool,
+    pub horizontally: bool,
+    pub across_layers: bool,
+}
+
+impl Wrapping {
+    pub fn any(&self) -> bool {
+        self.vertically || self.horizontally || self.across_layers
+    }
+}
+
+/// Weighted quick-union which also remembers the displacement of every node from its parent, as it would be if the
+/// lattice did not wrap around. When a union joins two nodes which are already connected, the cluster wraps around
+/// the lattice exactly if the two routes between them disagree about where the nodes are relative to each other.
+/// This is the approach of Newman and Ziff.
+#[derive(Debug)]
+pub struct WrappingUF {
+    id: Vec<usize>,
+    sz: Vec<usize>,
+    // displacement from each node to its parent
+    offset: Vec<Step>,
+    wrapping: Wrapping,
+}
+
+impl WrappingUF {
+    pub fn new(size: usize) -> WrappingUF {
+        WrappingUF {
+            id: (0..size).collect(),
+            sz: iter::repeat(1).take(size).collect(),
+            offset: iter::repeat([0, 0, 0]).take(size).collect(),
+            wrapping: Wrapping::default(),
+        }
+    }
+
+    /// Returns the number of bytes a union-find of `size` nodes would take up on the heap, without creating one.
+    pub fn memory_usage_of(size: usize) -> usize {
+        size * (2 * mem::size_of::<usize>() + mem::size_of::<Step>())
+    }
+
+    /// Returns the root of `i` along with the displacement from `i` to the root.
+    fn root(&self, mut i: usize) -> (usize, Step) {
+        let mut displacement = [0, 0, 0];
+        while i != self.id[i] {
+            displacement = add(displacement, self.offset[i]);
+            i = self.id[i];
+        }
+        (i, displacement)
+    }
+
+    /// Joins `p` to its neighbour `q`, which is `step` away from it.
+    pub fn union(&mut self, p: usize, q: usize, step: Step) {
+        let (i, p_to_i) = self.root(p);
+        let (j, q_to_j) = self.root(q);
+        // displacement from j to i, going from j to q, stepping back to p and then up to i
+        let j_to_i = add(subtract(p_to_i, step), negate(q_to_j));
+        if i == j {
+            self.wrapping.vertically |= j_to_i[0] != 0;
+            self.wrapping.horizontally |= j_to_i[1] != 0;
+            self.wrapping.across_layers |= j_to_i[2] != 0;
+        } else if self.sz[i] < self.sz[j] {
+            self.id[i] = j;
+            self.offset[i] = negate(j_to_i);
+            self.sz[j] += self.sz[i];
+        } else {
+            self.id[j] = i;
+            self.offset[j] = j_to_i;
+            self.sz[i] += self.sz[j];
+        }
+    }
+
+    pub fn connected(&self, p: usize, q: usize) -> bool {
+        self.root(p).0 == self.root(q).0
+    }
+
+    pub fn wrapping(&self) -> Wrapping {
+        self.wrapping
+    }
+
+    /// Returns the number of bytes the nodes take up on the heap.
+    pub fn memory_usage(&self) -> usize {
+        (self.id.capacity() + self.sz.capacity()) * mem::size_of::<usize>() +
+            self.offset.capacity() * mem::size_of::<Step>()
+    }
+}
+
+fn add(a: Step, b: Step) -> Step {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+fn subtract(a: Step, b: Step) -> Step {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn negate(a: Step) -> Step {
+    [-a[0], -a[1], -a[2]]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Wrapping, WrappingUF};
+
+    #[test]
+    fn ring_of_nodes_wraps_once_closed() {
+        // four nodes in a row on a lattice which wraps horizontally
+        let mut uf = WrappingUF::new(4);
+        uf.union(0, 1, [0, 1, 0]);
+        uf.union(2, 3, [0, 1, 0]);
+        uf.union(1, 2, [0, 1, 0]);
+        assert!(uf.connected(0, 3));
+        assert!(!uf.wrapping().any());
+
+        uf.union(3, 0, [0, 1, 0]);
+        assert_eq!(uf.wrapping(), Wrapping { vertically: false, horizontally: true, across_layers: false });
+    }
+
+    #[test]
+    fn closing_a_loop_without_wrapping_is_not_wrapping() {
+        // a 2x2 block: 0 1 on top of 2 3
+        let mut uf = WrappingUF::new(4);
+        uf.union(0, 1, [0, 1, 0]);
+        uf.union(1, 3, [1, 0, 0]);
+        uf.union(3, 2, [0, -1, 0]);
+        uf.union(2, 0, [-1, 0, 0]);
+        assert!(!uf.wrapping().any());
+    }
+}